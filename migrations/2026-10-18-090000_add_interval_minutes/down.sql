@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE power_states DROP COLUMN interval_minutes;
+
+ALTER TABLE price_cells DROP COLUMN interval_minutes;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+ALTER TABLE price_cells ADD COLUMN interval_minutes SMALLINT NOT NULL DEFAULT 60;
+
+ALTER TABLE power_states ADD COLUMN interval_minutes SMALLINT NOT NULL DEFAULT 60;
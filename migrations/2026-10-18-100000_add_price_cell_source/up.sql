@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE price_cells ADD COLUMN source TEXT;
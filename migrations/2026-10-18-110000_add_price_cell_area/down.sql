@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX area_moment_idx;
+CREATE INDEX moment_idx ON price_cells (moment_utc);
+
+ALTER TABLE price_cells DROP COLUMN area;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+ALTER TABLE price_cells ADD COLUMN area TEXT NOT NULL DEFAULT 'EE';
+ALTER TABLE price_cells ALTER COLUMN area DROP DEFAULT;
+
+DROP INDEX moment_idx;
+CREATE INDEX area_moment_idx ON price_cells (area, moment_utc);
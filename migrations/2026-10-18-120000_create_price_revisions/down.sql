@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE power_states DROP COLUMN stale;
+
+DROP TABLE price_revisions;
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+CREATE TABLE price_revisions (
+    id SERIAL PRIMARY KEY,
+    price_cell_id INTEGER NOT NULL REFERENCES price_cells (id) ON DELETE CASCADE,
+    old_price_mwh DECIMAL NOT NULL,
+    new_price_mwh DECIMAL NOT NULL,
+    old_source TEXT,
+    new_source TEXT,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+ALTER TABLE power_states ADD COLUMN stale BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+
+CREATE TABLE tariff_prices (
+    id SERIAL PRIMARY KEY,
+    valid_from TIMESTAMPTZ NOT NULL,
+    day_cents_kwh DECIMAL NOT NULL,
+    night_cents_kwh DECIMAL NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX valid_from_idx ON tariff_prices (valid_from);
+
+INSERT INTO tariff_prices (valid_from, day_cents_kwh, night_cents_kwh) VALUES
+    ('1970-01-01 00:00:00+00', 6.65, 3.86),
+    ('2022-06-01 00:00:00 Europe/Tallinn', 7.53, 4.38),
+    ('2022-12-01 00:00:00 Europe/Tallinn', 7.68, 4.47),
+    ('2023-01-01 00:00:00 Europe/Tallinn', 8.68, 5.05);
@@ -0,0 +1,26 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX package_band_valid_from_idx;
+
+ALTER TABLE tariff_prices
+    ADD COLUMN day_cents_kwh DECIMAL,
+    ADD COLUMN night_cents_kwh DECIMAL;
+
+UPDATE tariff_prices AS day SET
+    day_cents_kwh = day.cents_kwh,
+    night_cents_kwh = night.cents_kwh
+FROM tariff_prices AS night
+WHERE day.package = 'DayNight' AND day.band = 'day'
+    AND night.package = 'DayNight' AND night.band = 'night'
+    AND night.valid_from = day.valid_from;
+
+DELETE FROM tariff_prices WHERE day_cents_kwh IS NULL OR night_cents_kwh IS NULL;
+
+ALTER TABLE tariff_prices
+    DROP COLUMN package,
+    DROP COLUMN band,
+    DROP COLUMN cents_kwh,
+    ALTER COLUMN day_cents_kwh SET NOT NULL,
+    ALTER COLUMN night_cents_kwh SET NOT NULL;
+
+CREATE INDEX valid_from_idx ON tariff_prices (valid_from);
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+
+ALTER TABLE tariff_prices
+    ADD COLUMN package TEXT NOT NULL DEFAULT 'DayNight',
+    ADD COLUMN band TEXT NOT NULL DEFAULT 'day',
+    ADD COLUMN cents_kwh DECIMAL;
+
+UPDATE tariff_prices SET cents_kwh = day_cents_kwh;
+
+INSERT INTO tariff_prices
+    (valid_from, day_cents_kwh, night_cents_kwh, package, band, cents_kwh, created_at)
+SELECT valid_from, day_cents_kwh, night_cents_kwh, 'DayNight', 'night', night_cents_kwh, created_at
+FROM tariff_prices;
+
+ALTER TABLE tariff_prices
+    DROP COLUMN day_cents_kwh,
+    DROP COLUMN night_cents_kwh,
+    ALTER COLUMN cents_kwh SET NOT NULL,
+    ALTER COLUMN package DROP DEFAULT,
+    ALTER COLUMN band DROP DEFAULT;
+
+DROP INDEX valid_from_idx;
+CREATE INDEX package_band_valid_from_idx ON tariff_prices (package, band, valid_from);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE power_states ADD COLUMN reasons TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE price_cells ADD COLUMN tariff_mwh DECIMAL;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- Tariffs depend on the configured package and are attached when
+-- planning, a stored copy goes stale.
+ALTER TABLE price_cells DROP COLUMN tariff_mwh;
@@ -30,3 +30,15 @@ pub fn apply_power_state(connection: &mut PgConnection, state: &PowerState) -> e
     };
     Ok(())
 }
+
+/// Switches a secondary load by posting to its own webhook,
+/// `WEBHOOK_POST_ON_<NAME>` or `WEBHOOK_POST_OFF_<NAME>`.
+pub fn apply_secondary_state(name: &str, state: &PowerState) -> eyre::Result<()> {
+    let variable = match state {
+        PowerState::On => format!("WEBHOOK_POST_ON_{}", name.to_uppercase()),
+        PowerState::Off => format!("WEBHOOK_POST_OFF_{}", name.to_uppercase()),
+    };
+    let post_url = env::var(&variable).map_err(|e| eyre::eyre!("{}: {}", variable, e))?;
+    ureq::post(&post_url).call()?;
+    Ok(())
+}
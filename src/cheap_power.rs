@@ -0,0 +1,221 @@
+use chrono::{DateTime, Duration};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::{
+    price_cell::PriceCell,
+    price_components::PriceBasis,
+    price_matrix::DaySlice,
+    strategy::{constraints::DayConstraints, PowerState, PriceChangeUnit},
+};
+
+/// A load switched on only when power is cheap, such as a car
+/// charger or a second heater. It is switched by posting to the
+/// URLs in `WEBHOOK_POST_ON_<NAME>` and `WEBHOOK_POST_OFF_<NAME>`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SecondaryLoad {
+    pub name: String,
+    /// Hours a day the load runs at most, the cheapest first.
+    /// Defaults to every cheap hour.
+    pub max_hours: Option<u8>,
+}
+
+/// Hours priced at or below a threshold, usually negative prices,
+/// in which power is used whatever the day's strategy says.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CheapPowerConfig {
+    /// Defaults to 0 €/MWh.
+    pub below_mwh: Option<Decimal>,
+    /// Price compared against the threshold, `Spot` by default or
+    /// `Network` to include the network tariff.
+    pub price_basis: Option<PriceBasis>,
+    pub secondary_loads: Option<Vec<SecondaryLoad>>,
+}
+
+impl CheapPowerConfig {
+    /// Whether a published price is at or below the threshold.
+    /// Estimated prices never are.
+    pub fn is_cheap(&self, cell: &PriceCell) -> bool {
+        let basis = self.price_basis.unwrap_or(PriceBasis::Spot);
+        !cell.estimated && cell.price_on(basis).0 <= self.below_mwh.unwrap_or(Decimal::ZERO)
+    }
+
+    pub fn is_cheap_unit(&self, pcu: &PriceChangeUnit) -> bool {
+        pcu.price.is_some_and(|cell| self.is_cheap(cell))
+    }
+
+    fn turn_on(&self, pcu: &mut PriceChangeUnit) {
+        let basis = self.price_basis.unwrap_or(PriceBasis::Spot);
+        if let Some(cell) = pcu.price.filter(|cell| self.is_cheap(cell)) {
+            let reason = format!("Cheap power at {} €/MWh", cell.price_on(basis).0);
+            pcu.set_state(PowerState::On, reason);
+        }
+    }
+
+    /// Turns power on in every cheap unit.
+    pub fn force_on(&self, changes: &mut [PriceChangeUnit]) {
+        for pcu in changes.iter_mut() {
+            self.turn_on(pcu);
+        }
+    }
+
+    /// Applies the constraints of a day without switching off the
+    /// cheap units that are on.
+    pub fn apply_constraints(
+        &self,
+        changes: &mut [PriceChangeUnit],
+        constraints: &DayConstraints,
+        carried_off: Duration,
+    ) {
+        let kept: Vec<bool> = changes
+            .iter()
+            .map(|pcu| pcu.state == PowerState::On && self.is_cheap_unit(pcu))
+            .collect();
+        constraints.apply(changes, carried_off);
+        for (pcu, _) in changes.iter_mut().zip(kept).filter(|(_, kept)| *kept) {
+            if pcu.state == PowerState::Off {
+                self.turn_on(pcu);
+            }
+        }
+    }
+
+    pub fn secondary_loads(&self) -> &[SecondaryLoad] {
+        self.secondary_loads.as_deref().unwrap_or(&[])
+    }
+
+    /// The cheap cells of a day a secondary load runs in.
+    pub fn running_cells<'a>(
+        &self,
+        load: &SecondaryLoad,
+        prices: &'a DaySlice,
+    ) -> Vec<&'a PriceCell> {
+        let basis = self.price_basis.unwrap_or(PriceBasis::Spot);
+        let mut cheap: Vec<&PriceCell> =
+            prices.0.iter().filter(|cell| self.is_cheap(cell)).collect();
+        cheap.sort_by_key(|cell| cell.price_on(basis).0);
+        let Some(max_hours) = load.max_hours else {
+            return cheap;
+        };
+        let mut remaining = Duration::hours(max_hours.into());
+        cheap
+            .into_iter()
+            .take_while(|cell| {
+                let runs = remaining > Duration::zero();
+                remaining -= cell.interval;
+                runs
+            })
+            .collect()
+    }
+
+    /// State of a secondary load at a moment.
+    pub fn secondary_state(
+        &self,
+        load: &SecondaryLoad,
+        prices: &DaySlice,
+        moment: &DateTime<Tz>,
+    ) -> PowerState {
+        let running = self
+            .running_cells(load, prices)
+            .iter()
+            .any(|cell| cell.moment <= *moment && *moment < cell.end());
+        match running {
+            true => PowerState::On,
+            false => PowerState::Off,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Timelike;
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::{
+        constants::LOCAL_TZ,
+        price_matrix::PricePerMwh,
+        sample_data::tests::sample_day_specified,
+        strategy::{always::AlwaysOffStrategy, HourStrategy},
+    };
+
+    const PRICES: [Decimal; 6] = [
+        dec!(20),  // 1
+        dec!(-5),  // 2
+        dec!(0),   // 3
+        dec!(-20), // 4
+        dec!(3),   // 5
+        dec!(40),  // 6
+    ];
+
+    fn cheap_power(below_mwh: Decimal, price_basis: Option<PriceBasis>) -> CheapPowerConfig {
+        CheapPowerConfig {
+            below_mwh: Some(below_mwh),
+            price_basis,
+            secondary_loads: None,
+        }
+    }
+
+    fn hours(cells: &[&PriceCell]) -> Vec<u32> {
+        cells.iter().map(|cell| cell.moment.with_timezone(&LOCAL_TZ).hour()).collect()
+    }
+
+    #[test]
+    fn forces_cheap_hours_on() {
+        let day = sample_day_specified(&PRICES, 0);
+        let mut plan = AlwaysOffStrategy.plan_day(&day);
+        cheap_power(dec!(0), None).force_on(&mut plan);
+        let on: Vec<bool> = plan.iter().map(|pcu| pcu.state == PowerState::On).collect();
+        assert!(on == vec![false, true, true, true, false, false]);
+    }
+
+    #[test]
+    fn keeps_cheap_hours_on_despite_constraints() {
+        let day = sample_day_specified(&PRICES, 0);
+        let constraints = DayConstraints {
+            max_off_hours: None,
+            min_on_minutes: None,
+            min_off_minutes: None,
+            max_switches: Some(1),
+        };
+        let mut plan = AlwaysOffStrategy.plan_day(&day);
+        let config = cheap_power(dec!(0), None);
+        config.force_on(&mut plan);
+        let mut unguarded = plan.clone();
+        constraints.apply(&mut unguarded, Duration::zero());
+        assert!(unguarded.iter().all(|pcu| pcu.state == PowerState::Off));
+
+        config.apply_constraints(&mut plan, &constraints, Duration::zero());
+        let on: Vec<bool> = plan.iter().map(|pcu| pcu.state == PowerState::On).collect();
+        assert!(on == vec![false, true, true, true, false, false]);
+    }
+
+    #[test]
+    fn compares_on_basis() {
+        let mut day = sample_day_specified(&PRICES, 0);
+        for cell in day.0.iter_mut() {
+            cell.tariff_price = Some(PricePerMwh(dec!(10)));
+        }
+        assert!(!cheap_power(dec!(5), None).is_cheap(&day.0[0]));
+        assert!(cheap_power(dec!(5), None).is_cheap(&day.0[4]));
+        let network = cheap_power(dec!(5), Some(PriceBasis::Network));
+        assert!(network.is_cheap(&day.0[1]));
+        assert!(!network.is_cheap(&day.0[4]));
+        day.0[3].estimated = true;
+        assert!(!network.is_cheap(&day.0[3]));
+    }
+
+    #[test]
+    fn runs_secondary_loads_in_cheapest_hours() {
+        let day = sample_day_specified(&PRICES, 0);
+        let config = cheap_power(dec!(0), None);
+        let load = SecondaryLoad { name: "car".to_owned(), max_hours: Some(2) };
+        assert!(hours(&config.running_cells(&load, &day)) == vec![4, 2]);
+        let load = SecondaryLoad { name: "car".to_owned(), max_hours: None };
+        assert!(hours(&config.running_cells(&load, &day)) == vec![4, 2, 3]);
+        let moment = day.0[2].moment;
+        assert!(config.secondary_state(&load, &day, &moment) == PowerState::On);
+        let moment = day.0[4].moment;
+        assert!(config.secondary_state(&load, &day, &moment) == PowerState::Off);
+    }
+}
@@ -1,23 +1,40 @@
-use chrono::{DateTime, Utc, Weekday};
+use chrono::{DateTime, Duration, Utc, Weekday};
 use eyre::eyre;
 use diesel::{prelude::*, update, PgConnection};
+use rust_decimal::Decimal;
 use serde::Deserialize;
 
 use crate::{
-    constants::CVAR_CONFIG_FAILURE_COUNT,
+    cheap_power::CheapPowerConfig,
+    constants::{CVAR_CONFIG_FAILURE_COUNT, DEFAULT_AREA, HOURLY_INTERVAL},
     convars::{ConvarInt, NewConvarInt},
+    entsoe::EntsoeSource,
+    holidays::HolidayConfig,
+    nord_pool_data_portal::DataPortalSource,
+    nord_pool_spot_json::NordPoolSource,
+    price_components::{PriceBasis, PriceComponent},
+    price_source::PriceSource,
+    price_validation::ValidationConfig,
     schema::day_configurations,
     strategy::{
         always::{AlwaysOffStrategy, AlwaysOnStrategy},
+        block::BlockStrategy,
+        constraints::{ConstraintStep, DayConstraints},
+        cost_cap::CostCapStrategy,
         default::TariffStrategy,
+        energy::EnergyStrategy,
+        horizon::HorizonConfig,
         limit::PriceLimitStrategy,
         none::NoneStrategy,
         smart::SmartStrategy,
-        HourStrategy, MaskablePowerStrategy,
+        thermal::ThermalStrategy,
+        windows::WindowsStrategy,
+        HourStrategy, MaskablePowerStrategy, PriceChangeUnit,
     },
+    tariff::TariffPackage,
 };
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 #[serde(tag = "mode")]
 pub enum DayBasePlan {
     AlwaysOff(AlwaysOffStrategy),
@@ -26,43 +43,194 @@ pub enum DayBasePlan {
 }
 
 impl DayBasePlan {
-    pub fn get_hour_strategy(self) -> Box<dyn HourStrategy> {
+    pub fn get_hour_strategy(self, package: &TariffPackage) -> Box<dyn HourStrategy> {
         match self {
             DayBasePlan::AlwaysOff(v) => Box::new(v),
             DayBasePlan::AlwaysOn(v) => Box::new(v),
-            DayBasePlan::Tariff(v) => Box::new(v),
+            DayBasePlan::Tariff(mut v) => {
+                v.package = package.clone();
+                Box::new(v)
+            }
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 #[serde(tag = "mode")]
 pub enum DayStrategy {
     None(NoneStrategy),
     Limit(PriceLimitStrategy),
     Smart(SmartStrategy),
+    Block(BlockStrategy),
+    Windows(WindowsStrategy),
+    Thermal(ThermalStrategy),
+    Energy(EnergyStrategy),
+    CostCap(CostCapStrategy),
+    Constraints(DayConstraints),
 }
 
 impl DayStrategy {
-    pub fn get_day_strategy(self) -> Box<dyn MaskablePowerStrategy> {
+    /// `carried_off` is how long power was off when the day started,
+    /// which constraint steps count towards the longest time off.
+    pub fn get_day_strategy(
+        self,
+        config: &ConfigFile,
+        carried_off: Duration,
+    ) -> Box<dyn MaskablePowerStrategy> {
         match self {
             DayStrategy::None(v) => Box::new(v),
             DayStrategy::Limit(v) => Box::new(v),
-            DayStrategy::Smart(v) => Box::new(v),
+            DayStrategy::Smart(mut v) => {
+                v.cheap_power = config.cheap_power.clone();
+                Box::new(v)
+            }
+            DayStrategy::Block(v) => Box::new(v),
+            DayStrategy::Windows(v) => Box::new(v),
+            DayStrategy::Thermal(v) => Box::new(v),
+            DayStrategy::Energy(mut v) => {
+                v.power_kw = config.power_kw;
+                Box::new(v)
+            }
+            DayStrategy::CostCap(mut v) => {
+                v.power_kw = config.power_kw;
+                Box::new(v)
+            }
+            DayStrategy::Constraints(constraints) => Box::new(ConstraintStep {
+                constraints,
+                carried_off,
+            }),
+        }
+    }
+
+    /// Whether the step only sets how long power is on in the day,
+    /// so that on-time may move between the days of a horizon.
+    pub fn is_budget_only(&self) -> bool {
+        match self {
+            DayStrategy::Smart(_) => true,
+            DayStrategy::Energy(v) => v.is_daily_only(),
+            _ => false,
+        }
+    }
+
+    /// Whether moving on-time across midnight may switch the unit.
+    pub fn may_shift(&self, pcu: &PriceChangeUnit) -> bool {
+        match self {
+            DayStrategy::Smart(v) => v.may_shift(pcu),
+            _ => true,
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum PriceSourceConfig {
+    NordPool,
+    DataPortal,
+    Entsoe,
+}
+
+impl PriceSourceConfig {
+    pub fn get_price_source(self) -> Box<dyn PriceSource> {
+        match self {
+            PriceSourceConfig::NordPool => Box::new(NordPoolSource),
+            PriceSourceConfig::DataPortal => Box::new(DataPortalSource),
+            PriceSourceConfig::Entsoe => Box::new(EntsoeSource),
+        }
+    }
+}
+
+const DEFAULT_PRICE_SOURCES: [PriceSourceConfig; 2] =
+    [PriceSourceConfig::DataPortal, PriceSourceConfig::Entsoe];
+
 #[derive(Debug, Deserialize)]
 pub struct Day {
     pub hours_always_on: Option<Vec<u8>>,
     pub hours_always_off: Option<Vec<u8>>,
     pub base: Option<DayBasePlan>,
     pub strategy: Option<DayStrategy>,
+    /// Strategies applied in order after `strategy`, each to the
+    /// states planned by the one before.
+    pub steps: Option<Vec<DayStrategy>>,
+    pub constraints: Option<DayConstraints>,
+}
+
+impl Day {
+    /// Every masking step of the day in the order they apply.
+    pub fn steps(&self) -> Vec<DayStrategy> {
+        self.strategy
+            .iter()
+            .chain(self.steps.iter().flatten())
+            .cloned()
+            .collect()
+    }
+
+    /// Runs every strategy step of the day over a base plan in turn.
+    pub fn plan_steps<'a>(
+        &self,
+        config: &ConfigFile,
+        base: Vec<PriceChangeUnit<'a>>,
+        carried_off: Duration,
+    ) -> Vec<PriceChangeUnit<'a>> {
+        self.steps().into_iter().fold(base, |plan, step| {
+            step.get_day_strategy(config, carried_off).plan_day_masked(&plan)
+        })
+    }
+
+    /// Whether every step of the day is budget only, so that the
+    /// horizon may move on-time into or out of it.
+    pub fn may_rebalance(&self) -> bool {
+        let steps = self.steps();
+        !steps.is_empty() && steps.iter().all(DayStrategy::is_budget_only)
+    }
+
+    /// Whether planning the day depends on how long power was off
+    /// the day before.
+    pub fn needs_carried_off(&self) -> bool {
+        let limits_off = |c: &DayConstraints| c.max_off_hours.is_some();
+        self.constraints.as_ref().is_some_and(limits_off)
+            || self.steps().iter().any(|step| match step {
+                DayStrategy::Constraints(c) => limits_off(c),
+                _ => false,
+            })
+    }
 }
 
 #[derive(Deserialize)]
 pub struct ConfigFile {
+    /// Length of a planned interval in minutes. Prices coarser
+    /// than this are expanded to it. Defaults to an hour.
+    pub resolution_minutes: Option<i64>,
+    /// Price sources to fetch from, tried in order until one
+    /// returns a complete day.
+    pub price_sources: Option<Vec<PriceSourceConfig>>,
+    /// Power the switched device draws in kW, which energy targets
+    /// and costs are counted by.
+    pub power_kw: Option<Decimal>,
+    /// Price the projected cost of a day is counted in. Defaults to
+    /// the basis of the day's CostCap step, otherwise to `Consumer`
+    /// with price components configured and `Network` without.
+    pub cost_basis: Option<PriceBasis>,
+    /// Delivery area the installation is in, e.g. `EE` or `SE3`.
+    /// Defaults to `EE`.
+    pub area: Option<String>,
+    /// Plausibility checks applied to fetched and imported prices.
+    pub validation: Option<ValidationConfig>,
+    /// Plan intervals without a published price using prices
+    /// estimated from history. Defaults to false.
+    pub estimate_prices: Option<bool>,
+    /// Network tariff package the installation is on. Defaults
+    /// to day and night rates.
+    pub tariff: Option<TariffPackage>,
+    /// Seller margin, fees, excise and VAT making up the consumer
+    /// price along with the spot price and network tariff.
+    pub price_components: Option<Vec<PriceComponent>>,
+    /// Holidays the tariff package treats apart from working days.
+    pub holidays: Option<HolidayConfig>,
+    /// Plan today and tomorrow together once tomorrow's prices
+    /// are known. Days are planned apart if left out.
+    pub horizon: Option<HorizonConfig>,
+    /// Hours cheap enough to turn power on in whatever the day's
+    /// strategy says, and the loads run only in them.
+    pub cheap_power: Option<CheapPowerConfig>,
     pub monday: Day,
     pub tuesday: Day,
     pub wednesday: Day,
@@ -84,6 +252,53 @@ impl ConfigFile {
         ConfigFile::decode_config(&conf)
     }
 
+    pub fn resolution(&self) -> Duration {
+        self.resolution_minutes
+            .map(Duration::minutes)
+            .unwrap_or(HOURLY_INTERVAL)
+    }
+
+    pub fn area(&self) -> &str {
+        self.area.as_deref().unwrap_or(DEFAULT_AREA)
+    }
+
+    pub fn validation(&self) -> ValidationConfig {
+        self.validation.clone().unwrap_or_default()
+    }
+
+    pub fn tariff_package(&self) -> eyre::Result<TariffPackage> {
+        let mut package = self.tariff.clone().unwrap_or_default();
+        package.validate()?;
+        package.holidays = self.holidays.clone().unwrap_or_default().calendar()?;
+        Ok(package)
+    }
+
+    /// Price the projected cost of the given day is counted in.
+    pub fn cost_basis(&self, day: &Day) -> PriceBasis {
+        let cost_cap = day.steps().into_iter().find_map(|step| match step {
+            DayStrategy::CostCap(v) => Some(v.price_basis()),
+            _ => None,
+        });
+        match (self.cost_basis, cost_cap) {
+            (Some(basis), _) | (None, Some(basis)) => basis,
+            (None, None) if !self.price_components().is_empty() => PriceBasis::Consumer,
+            (None, None) => PriceBasis::Network,
+        }
+    }
+
+    pub fn price_components(&self) -> &[PriceComponent] {
+        self.price_components.as_deref().unwrap_or(&[])
+    }
+
+    pub fn get_price_sources(&self) -> Vec<Box<dyn PriceSource>> {
+        self.price_sources
+            .as_deref()
+            .unwrap_or(&DEFAULT_PRICE_SOURCES)
+            .iter()
+            .map(|source| source.get_price_source())
+            .collect()
+    }
+
     pub fn get_day(&self, weekday: &Weekday) -> &Day {
         match weekday {
             Weekday::Mon => &self.monday,
@@ -258,9 +473,15 @@ struct NewConfigFileDB<'a> {
 
 #[cfg(test)]
 pub mod tests {
+    use rust_decimal_macros::dec;
     use serial_test::serial;
 
-    use crate::{database, constants::DEFAULT_CONFIG_FILENAME};
+    use crate::{
+        constants::DEFAULT_CONFIG_FILENAME,
+        database,
+        sample_data::tests::sample_day_specified,
+        strategy::PowerState,
+    };
 
     use super::*;
     use crate::schema::day_configurations::dsl::*;
@@ -345,4 +566,105 @@ pub mod tests {
         assert!(db_good.known_broken == false);
         assert!(db_bad.known_broken == true);
     }
+
+    const PIPELINE_TOML: &str = r#"
+        [monday.strategy]
+        mode = "Limit"
+        limit_mwh = 150
+
+        [[monday.steps]]
+        mode = "Smart"
+        hour_budget = 8
+        morning_hours = 2
+        hard_limit_mwh = 300
+
+        [[monday.steps]]
+        mode = "Constraints"
+        max_off_hours = 6
+
+        [tuesday]
+        [wednesday]
+        [thursday]
+        [friday]
+        [saturday]
+        [sunday]
+    "#;
+
+    #[test]
+    fn decodes_step_pipeline() {
+        let config = ConfigFile::decode_config(PIPELINE_TOML).unwrap();
+        let steps = config.monday.steps();
+        assert!(steps.len() == 3);
+        assert!(matches!(steps[0], DayStrategy::Limit(_)));
+        assert!(matches!(steps[2], DayStrategy::Constraints(_)));
+        assert!(config.monday.needs_carried_off());
+        assert!(config.tuesday.steps().is_empty());
+        assert!(!config.tuesday.needs_carried_off());
+    }
+
+    const COST_CAP_TOML: &str = r#"
+        [tuesday.strategy]
+        mode = "CostCap"
+        max_eur = 2
+        price_basis = "Spot"
+    "#;
+
+    #[test]
+    fn counts_cost_on_cost_cap_basis() {
+        let config = ConfigFile::decode_config(PIPELINE_TOML).unwrap();
+        assert!(config.cost_basis(&config.monday) == PriceBasis::Network);
+
+        let days = PIPELINE_TOML.replace("[tuesday]", COST_CAP_TOML);
+        let body = format!("[[price_components]]\nkind = \"Vat\"\nvalue = 24\n{}", days);
+        let config = ConfigFile::decode_config(&body).unwrap();
+        assert!(config.cost_basis(&config.monday) == PriceBasis::Consumer);
+        assert!(config.cost_basis(&config.tuesday) == PriceBasis::Spot);
+
+        let body = format!("cost_basis = \"Network\"\n{}", body);
+        let config = ConfigFile::decode_config(&body).unwrap();
+        assert!(config.cost_basis(&config.tuesday) == PriceBasis::Network);
+    }
+
+    /// Starts at local 1:00, with six hours under the limit of 150.
+    const PIPELINE_PRICES: [Decimal; 24] = [
+        dec!(210),
+        dec!(220),
+        dec!(100), // 3
+        dec!(230),
+        dec!(240),
+        dec!(250),
+        dec!(120), // 7
+        dec!(200),
+        dec!(190),
+        dec!(180),
+        dec!(90), // 11
+        dec!(170),
+        dec!(160),
+        dec!(140), // 14
+        dec!(175),
+        dec!(185),
+        dec!(195),
+        dec!(110), // 18
+        dec!(205),
+        dec!(215),
+        dec!(225),
+        dec!(130), // 22
+        dec!(235),
+        dec!(245),
+    ];
+
+    #[test]
+    fn keeps_limit_of_earlier_step() {
+        let config = ConfigFile::decode_config(PIPELINE_TOML).unwrap();
+        let day = sample_day_specified(&PIPELINE_PRICES, 0);
+        let base = TariffStrategy::default().plan_day(&day);
+        let plan = config.monday.plan_steps(&config, base, Duration::zero());
+        // The Smart budget of 8 hours is left with six hours to spend
+        let on: Vec<usize> = (0..plan.len()).filter(|i| plan[*i].state == PowerState::On).collect();
+        assert!(on == vec![2, 6, 10, 13, 17, 21]);
+        for pcu in plan.iter().filter(|pcu| pcu.state == PowerState::Off) {
+            assert!(pcu.held_off);
+            assert!(pcu.reasons[1].starts_with("Limit:"));
+        }
+    }
 }
@@ -1,13 +1,8 @@
-use std::ops::Range;
-
+use chrono::Duration;
 use chrono_tz::{
     Europe::{Berlin, Tallinn},
     Tz,
 };
-use lazy_static::lazy_static;
-use rust_decimal_macros::dec;
-
-use crate::price_matrix::CentsPerKwh;
 
 pub const DEFAULT_CONFIG_FILENAME: &str = "/etc/kiel.d/default.toml";
 
@@ -19,19 +14,24 @@ pub const LOCAL_TZ: Tz = Tallinn;
 
 pub const PLANNING_TZ: Tz = MARKET_TZ;
 
-pub const HOURS_OF_DAY: Range<u8> = Range { start: 0, end: 24 };
-
-lazy_static! {
-    pub static ref DAY_TARIFF_PRICE: CentsPerKwh = CentsPerKwh(dec!(6.65));
-    pub static ref NIGHT_TARIFF_PRICE: CentsPerKwh = CentsPerKwh(dec!(3.86));
-    pub static ref DAY_TARIFF_PRICE_JUNE_2022: CentsPerKwh = CentsPerKwh(dec!(7.31));
-    pub static ref NIGHT_TARIFF_PRICE_JUNE_2022: CentsPerKwh = CentsPerKwh(dec!(4.25));
-    pub static ref DAY_TARIFF_PRICE_OCTOBER_2022: CentsPerKwh = CentsPerKwh(dec!(7.53));
-    pub static ref NIGHT_TARIFF_PRICE_OCTOBER_2022: CentsPerKwh = CentsPerKwh(dec!(4.38));
-    pub static ref DAY_TARIFF_PRICE_DECEMBER_2022: CentsPerKwh = CentsPerKwh(dec!(7.68));
-    pub static ref NIGHT_TARIFF_PRICE_DECEMBER_2022: CentsPerKwh = CentsPerKwh(dec!(4.47));
-    pub static ref DAY_TARIFF_PRICE_JANUARY_2023: CentsPerKwh = CentsPerKwh(dec!(8.68));
-    pub static ref NIGHT_TARIFF_PRICE_JANUARY_2023: CentsPerKwh = CentsPerKwh(dec!(5.05));
-}
+pub const DEFAULT_AREA: &str = "EE";
+
+pub const HOURLY_INTERVAL: Duration = Duration::hours(1);
+
+/// Upper bound of planned intervals in a single day:
+/// 25 hours on DST change days, in quarter-hours.
+pub const MAX_INTERVALS_PER_DAY: i64 = 25 * 4;
 
 pub const CVAR_CONFIG_FAILURE_COUNT: &str = "config_failures";
+
+pub const CVAR_PRICE_VALIDATION_FAILED: &str = "price_validation_failed";
+
+pub const CVAR_PRICE_QUARANTINE: &str = "price_quarantine";
+
+pub const CVAR_FETCH_FAILURES: &str = "fetch_failures";
+
+pub const CVAR_TOMORROW_MISSING: &str = "tomorrow_missing";
+
+pub const CVAR_LAST_FETCH_ATTEMPT: &str = "last_fetch_attempt";
+
+pub const CVAR_LAST_SUCCESSFUL_FETCH: &str = "last_successful_fetch";
@@ -1,4 +1,5 @@
 use chrono::{Utc, DateTime};
+use diesel::{ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
 use crate::schema::{convar_ints, convar_strings};
 
 #[derive(Queryable)]
@@ -29,4 +30,42 @@ pub struct ConvarString {
 pub struct NewConvarString<'a> {
     pub key: &'a str,
     pub value: &'a str,
-}
\ No newline at end of file
+}
+
+/// Reads the latest value of an integer convar.
+pub fn get_int(connection: &mut PgConnection, name: &str) -> eyre::Result<Option<i32>> {
+    use crate::schema::convar_ints::dsl::*;
+
+    let row = convar_ints
+        .filter(key.eq(name))
+        .order(id.desc())
+        .first::<ConvarInt>(connection)
+        .optional()?;
+    Ok(row.map(|c| c.value))
+}
+
+pub fn set_int(connection: &mut PgConnection, name: &str, new_value: i32) -> eyre::Result<()> {
+    diesel::insert_into(convar_ints::table)
+        .values(NewConvarInt { key: name, value: new_value })
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Reads the latest value of a string convar.
+pub fn get_string(connection: &mut PgConnection, name: &str) -> eyre::Result<Option<String>> {
+    use crate::schema::convar_strings::dsl::*;
+
+    let row = convar_strings
+        .filter(key.eq(name))
+        .order(id.desc())
+        .first::<ConvarString>(connection)
+        .optional()?;
+    Ok(row.map(|c| c.value))
+}
+
+pub fn set_string(connection: &mut PgConnection, name: &str, new_value: &str) -> eyre::Result<()> {
+    diesel::insert_into(convar_strings::table)
+        .values(NewConvarString { key: name, value: new_value })
+        .execute(connection)?;
+    Ok(())
+}
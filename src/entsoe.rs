@@ -0,0 +1,365 @@
+use chrono::{DateTime, Duration, NaiveDateTime, Timelike, Utc};
+use eyre::eyre;
+use roxmltree::{Document, Node};
+use std::{collections::BTreeMap, env};
+
+use crate::{
+    constants::MARKET_TZ,
+    nord_pool_meta::{convert_price_to_decimal, parse_date},
+    price_cell::PriceCell,
+    price_matrix::{DateColumn, PriceMatrix, PricePerMwh},
+    price_source::PriceSource,
+};
+
+const DEFAULT_ENTSOE_URI: &str = "https://web-api.tp.entsoe.eu/api";
+
+/// Curve type where positions repeating the previous
+/// price are left out of the period.
+const CURVE_VARIABLE_BLOCKS: &str = "A03";
+
+/// Energy Identification Codes of the bidding zones
+/// ENTSO-E publishes day-ahead prices for.
+const AREA_EICS: [(&str, &str); 9] = [
+    ("EE", "10Y1001A1001A39I"),
+    ("FI", "10YFI-1--------U"),
+    ("LV", "10YLV-1001A00074"),
+    ("LT", "10YLT-1001A0008Q"),
+    ("SE1", "10Y1001A1001A44P"),
+    ("SE2", "10Y1001A1001A45N"),
+    ("SE3", "10Y1001A1001A46L"),
+    ("SE4", "10Y1001A1001A47J"),
+    ("DE-LU", "10Y1001A1001A82H"),
+];
+
+pub fn area_eic(area: &str) -> eyre::Result<&'static str> {
+    AREA_EICS
+        .iter()
+        .find(|(code, _)| *code == area)
+        .map(|(_, eic)| *eic)
+        .ok_or(eyre!("No ENTSO-E EIC known for area {}", area))
+}
+
+pub fn eic_area(eic: &str) -> eyre::Result<&'static str> {
+    AREA_EICS
+        .iter()
+        .find(|(_, code)| *code == eic)
+        .map(|(area, _)| *area)
+        .ok_or(eyre!("No area known for ENTSO-E EIC {}", eic))
+}
+
+fn child<'a, 'input>(node: &Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|c| c.tag_name().name() == name)
+}
+
+fn child_text<'a>(node: &Node<'a, '_>, name: &str) -> eyre::Result<&'a str> {
+    child(node, name)
+        .and_then(|c| c.text())
+        .map(str::trim)
+        .ok_or(eyre!("Missing <{}> in <{}>", name, node.tag_name().name()))
+}
+
+/// Parses ENTSO-E interval timestamps, which leave out
+/// the seconds, e.g. `2024-10-09T22:00Z`.
+fn parse_entsoe_time(text: &str) -> eyre::Result<DateTime<Utc>> {
+    let naive = NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%MZ")?;
+    Ok(naive.and_utc())
+}
+
+/// Parses an ISO 8601 resolution such as `PT60M`, `PT15M` or `PT1H`.
+fn parse_resolution(text: &str) -> eyre::Result<Duration> {
+    let err = || eyre!("Unsupported resolution: {}", text);
+    let value = text.strip_prefix("PT").ok_or_else(err)?;
+    let resolution = if let Some(minutes) = value.strip_suffix('M') {
+        Duration::minutes(minutes.parse()?)
+    } else if let Some(hours) = value.strip_suffix('H') {
+        Duration::hours(hours.parse()?)
+    } else {
+        return Err(err());
+    };
+    if resolution <= Duration::zero() || 60 % resolution.num_minutes() != 0 {
+        return Err(err());
+    }
+    Ok(resolution)
+}
+
+/// Reads the prices of a single `Period`, filling in positions
+/// left out under the A03 curve type with the previous price.
+fn decode_period(period: &Node, curve_type: &str, area: &str) -> eyre::Result<Vec<PriceCell>> {
+    let interval = child(period, "timeInterval").ok_or(eyre!("Missing <timeInterval>"))?;
+    let start = parse_entsoe_time(child_text(&interval, "start")?)?;
+    let end = parse_entsoe_time(child_text(&interval, "end")?)?;
+    let resolution = parse_resolution(child_text(period, "resolution")?)?;
+
+    let mut points = BTreeMap::new();
+    for point in period.children().filter(|c| c.tag_name().name() == "Point") {
+        let position: i32 = child_text(&point, "position")?.parse()?;
+        let price = convert_price_to_decimal(child_text(&point, "price.amount")?)?;
+        points.insert(position, price);
+    }
+
+    let position_count = ((end - start).num_minutes() / resolution.num_minutes()) as i32;
+    let mut cells = vec![];
+    let mut previous = None;
+    for position in 1..=position_count {
+        let price = match points.get(&position) {
+            Some(price) => *price,
+            None if curve_type == CURVE_VARIABLE_BLOCKS => {
+                previous.ok_or(eyre!("Period starting {} has no first position", start))?
+            }
+            None => continue,
+        };
+        previous = Some(price);
+
+        let moment = (start + resolution * (position - 1)).with_timezone(&MARKET_TZ);
+        cells.push(PriceCell {
+            price: PricePerMwh(price),
+            moment,
+            tariff_price: None,
+            market_hour: moment.hour(),
+            interval: resolution,
+            source: None,
+            area: area.to_owned(),
+            estimated: false,
+            breakdown: None,
+        });
+    }
+    Ok(cells)
+}
+
+/// Decodes an ENTSO-E `Publication_MarketDocument` of day-ahead
+/// prices (document type A44) into a `PriceMatrix`. The area of
+/// every cell is taken from the `in_Domain` of its time series.
+///
+/// When a day is published in several resolutions, only the finest
+/// one is kept.
+pub fn decode_xml(body: &str) -> eyre::Result<PriceMatrix> {
+    let document = Document::parse(body)?;
+    let root = document.root_element();
+
+    if root.tag_name().name() == "Acknowledgement_MarketDocument" {
+        let reason = child(&root, "Reason")
+            .and_then(|r| child_text(&r, "text").ok())
+            .unwrap_or("no reason given");
+        return Err(eyre!("ENTSO-E returned no prices: {}", reason));
+    }
+    if root.tag_name().name() != "Publication_MarketDocument" {
+        return Err(eyre!("Unexpected document: {}", root.tag_name().name()));
+    }
+
+    let mut date_map: BTreeMap<String, DateColumn> = BTreeMap::new();
+
+    for series in root.children().filter(|c| c.tag_name().name() == "TimeSeries") {
+        let curve_type = child_text(&series, "curveType").unwrap_or(CURVE_VARIABLE_BLOCKS);
+        let area = eic_area(child_text(&series, "in_Domain.mRID")?)?;
+        for period in series.children().filter(|c| c.tag_name().name() == "Period") {
+            for cell in decode_period(&period, curve_type, area)? {
+                let dateline = cell.moment.format("%Y-%m-%d").to_string();
+                if !date_map.contains_key(&dateline) {
+                    let formal_date = parse_date(&dateline, &MARKET_TZ)?;
+                    date_map.insert(dateline.clone(), DateColumn::new(formal_date));
+                }
+                date_map.get_mut(&dateline).unwrap().cells.0.push(cell);
+            }
+        }
+    }
+
+    let map_to_vec = date_map
+        .into_values()
+        .map(|mut column| {
+            let finest = column.cells.0.iter().map(|c| c.interval).min();
+            column.cells.0.retain(|c| Some(c.interval) == finest);
+            column.cells.0.sort_by_key(|c| c.moment);
+            Some(column)
+        })
+        .collect();
+    Ok(map_to_vec)
+}
+
+fn format_period(moment: &DateTime<Utc>) -> String {
+    moment.format("%Y%m%d%H%M").to_string()
+}
+
+/// Requests day-ahead prices of a bidding zone between the given
+/// moments from an ENTSO-E Transparency Platform compatible endpoint.
+pub fn fetch_xml(
+    uri: &str,
+    token: &str,
+    eic: &str,
+    start: &DateTime<Utc>,
+    end: &DateTime<Utc>,
+) -> eyre::Result<PriceMatrix> {
+    let response = ureq::get(uri)
+        .query("securityToken", token)
+        .query("documentType", "A44")
+        .query("in_Domain", eic)
+        .query("out_Domain", eic)
+        .query("periodStart", &format_period(start))
+        .query("periodEnd", &format_period(end))
+        .call();
+
+    // Missing data is answered with an acknowledgement document,
+    // sometimes with an error status attached.
+    let body = match response {
+        Ok(response) => response.into_string()?,
+        Err(ureq::Error::Status(_, response)) => response.into_string()?,
+        Err(e) => return Err(e.into()),
+    };
+    decode_xml(&body)
+}
+
+/// Fetches today's and tomorrow's day-ahead prices of an area from
+/// ENTSO-E, authenticating with `ENTSOE_TOKEN`. The endpoint can be
+/// replaced with `ENTSOE_URI`.
+pub fn fetch_xml_from_entsoe(area: &str) -> eyre::Result<PriceMatrix> {
+    let uri = env::var("ENTSOE_URI").unwrap_or(DEFAULT_ENTSOE_URI.to_owned());
+    let token = env::var("ENTSOE_TOKEN")?;
+    let eic = area_eic(area)?;
+
+    let today = Utc::now().with_timezone(&MARKET_TZ).date_naive();
+    let start = today
+        .and_hms_opt(0, 0, 0)
+        .and_then(|dt| dt.and_local_timezone(MARKET_TZ).earliest())
+        .ok_or(eyre!("Unable to find start of day {}", today))?
+        .with_timezone(&Utc);
+    let end = start + Duration::days(2);
+
+    let date_vectors = fetch_xml(&uri, &token, eic, &start, &end)?;
+
+    Ok(date_vectors)
+}
+
+/// The ENTSO-E Transparency Platform.
+pub struct EntsoeSource;
+
+impl PriceSource for EntsoeSource {
+    fn name(&self) -> &'static str {
+        "Entsoe"
+    }
+
+    fn fetch(&self, area: &str) -> eyre::Result<PriceMatrix> {
+        fetch_xml_from_entsoe(area)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use chrono::{Datelike, TimeZone};
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn sample_matrix(filename: &str) -> PriceMatrix {
+        let body = std::fs::read_to_string(filename).unwrap();
+        decode_xml(&body).unwrap()
+    }
+
+    /// Serves a single HTTP response with the given body and hands
+    /// back the request line it received.
+    fn stub_server(body: String) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}/api", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0; 4096];
+            let read = stream.read(&mut buffer).unwrap();
+            let request = String::from_utf8_lossy(&buffer[..read]).to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/xml\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+            request.lines().next().unwrap_or_default().to_owned()
+        });
+        (uri, handle)
+    }
+
+    #[test]
+    fn decodes_hourly_days() {
+        let matrix = sample_matrix("samples/entsoe_a44.xml");
+        assert!(matrix.len() == 2);
+        let first = matrix[0].as_ref().unwrap();
+        assert!(first.date.day() == 10);
+        assert!(first.cells.0.len() == 24);
+        let midnight = MARKET_TZ.with_ymd_and_hms(2024, 10, 10, 0, 0, 0).unwrap();
+        assert!(first.cells.0[0].moment == midnight);
+        assert!(first.cells.0[0].price == PricePerMwh(dec!(58.07)));
+        assert!(first.cells.0[0].interval == Duration::hours(1));
+        let second = matrix[1].as_ref().unwrap();
+        assert!(second.cells.0.len() == 24);
+        assert!(second.cells.0.iter().all(|c| c.area == "EE"));
+    }
+
+    #[test]
+    fn fills_curve_gaps() {
+        let matrix = sample_matrix("samples/entsoe_a44.xml");
+        let first = &matrix[0].as_ref().unwrap().cells.0;
+        assert!(first[2].price == PricePerMwh(dec!(107.08)));
+        assert!(first[2].market_hour == 2);
+        assert!(first[13].price == PricePerMwh(dec!(153.83)));
+        assert!(first[14].price == PricePerMwh(dec!(153.83)));
+        assert!(first[15].price == PricePerMwh(dec!(44.1)));
+        let second = &matrix[1].as_ref().unwrap().cells.0;
+        assert!(second[23].price == PricePerMwh(dec!(50.69)));
+    }
+
+    #[test]
+    fn prefers_quarter_hours() {
+        let matrix = sample_matrix("samples/entsoe_a44_15min.xml");
+        assert!(matrix.len() == 1);
+        let cells = &matrix[0].as_ref().unwrap().cells.0;
+        assert!(cells.len() == 96);
+        assert!(cells.iter().all(|c| c.interval == Duration::minutes(15)));
+        assert!(cells[2].price == PricePerMwh(dec!(121.01)));
+        assert!(cells[3].price == PricePerMwh(dec!(186.5)));
+        assert!(cells[50].price == PricePerMwh(dec!(81.78)));
+        assert!(cells[50].market_hour == 12);
+    }
+
+    #[test]
+    fn reports_acknowledgement() {
+        let body = std::fs::read_to_string("samples/entsoe_acknowledgement.xml").unwrap();
+        let result = decode_xml(&body);
+        assert!(result.unwrap_err().to_string().contains("No matching data"));
+    }
+
+    #[test]
+    fn maps_areas_to_eics() {
+        assert!(area_eic("SE3").unwrap() == "10Y1001A1001A46L");
+        assert!(eic_area("10YFI-1--------U").unwrap() == "FI");
+        assert!(area_eic("XX").is_err());
+        assert!(eic_area("10YXX").is_err());
+    }
+
+    #[test]
+    fn parses_resolutions() {
+        assert!(parse_resolution("PT60M").unwrap() == Duration::hours(1));
+        assert!(parse_resolution("PT15M").unwrap() == Duration::minutes(15));
+        assert!(parse_resolution("PT1H").unwrap() == Duration::hours(1));
+        assert!(parse_resolution("P1D").is_err());
+        assert!(parse_resolution("PT7M").is_err());
+    }
+
+    #[test]
+    fn fetches_from_stub() {
+        let body = std::fs::read_to_string("samples/entsoe_a44.xml").unwrap();
+        let (uri, handle) = stub_server(body);
+        let start = Utc.with_ymd_and_hms(2024, 10, 9, 22, 0, 0).unwrap();
+        let end = start + Duration::days(2);
+        let eic = area_eic("EE").unwrap();
+        let matrix = fetch_xml(&uri, "token", eic, &start, &end).unwrap();
+        assert!(matrix.len() == 2);
+
+        let request = handle.join().unwrap();
+        assert!(request.contains("documentType=A44"));
+        assert!(request.contains("in_Domain=10Y1001A1001A39I"));
+        assert!(request.contains("periodStart=202410092200"));
+        assert!(request.contains("periodEnd=202410112200"));
+    }
+}
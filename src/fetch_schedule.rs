@@ -0,0 +1,221 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use diesel::PgConnection;
+use eyre::eyre;
+
+use crate::{
+    config_file::ConfigFile,
+    constants::{
+        CVAR_FETCH_FAILURES, CVAR_LAST_FETCH_ATTEMPT, CVAR_LAST_SUCCESSFUL_FETCH,
+        CVAR_TOMORROW_MISSING, MARKET_TZ, PLANNING_TZ,
+    },
+    convars,
+    nord_pool_meta::parse_date,
+    price_cell::PriceCell,
+    price_matrix::{self, DateColumn},
+    price_source,
+};
+
+/// Day-ahead auction results are published at around 12:45 CET
+/// on the day before delivery.
+const PUBLICATION_HOUR: u32 = 12;
+const PUBLICATION_MINUTE: u32 = 45;
+
+const FIRST_RETRY: Duration = Duration::minutes(5);
+const MAX_RETRY: Duration = Duration::hours(1);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FetchDecision {
+    /// Every price of the delivery day is already stored.
+    Complete,
+    /// The auction results are not expected until then.
+    TooEarly(DateTime<Tz>),
+    /// The last attempt failed and the next one waits until then.
+    Backoff(DateTime<Utc>),
+    Fetch,
+}
+
+/// The moment the day-ahead prices of `delivery` are expected.
+pub fn expected_publication(delivery: &NaiveDate) -> eyre::Result<DateTime<Tz>> {
+    let auction_day = delivery
+        .pred_opt()
+        .ok_or(eyre!("No day before {}", delivery))?;
+    auction_day
+        .and_hms_opt(PUBLICATION_HOUR, PUBLICATION_MINUTE, 0)
+        .and_then(|dt| dt.and_local_timezone(MARKET_TZ).earliest())
+        .ok_or(eyre!("Unable to find publication time of {}", delivery))
+}
+
+/// Time to wait after the given number of consecutive failed
+/// attempts: doubled every time, up to an hour.
+pub fn retry_delay(failures: i32) -> Duration {
+    if failures <= 0 {
+        return Duration::zero();
+    }
+    let doublings = (failures - 1).min(8) as u32;
+    (FIRST_RETRY * 2i32.pow(doublings)).min(MAX_RETRY)
+}
+
+pub fn decide(
+    now: &DateTime<Utc>,
+    delivery: &NaiveDate,
+    complete: bool,
+    failures: i32,
+    last_attempt: Option<DateTime<Utc>>,
+) -> eyre::Result<FetchDecision> {
+    if complete {
+        return Ok(FetchDecision::Complete);
+    }
+    let publication = expected_publication(delivery)?;
+    if *now < publication {
+        return Ok(FetchDecision::TooEarly(publication));
+    }
+    if let Some(last_attempt) = last_attempt {
+        let next_attempt = last_attempt + retry_delay(failures);
+        if *now < next_attempt {
+            return Ok(FetchDecision::Backoff(next_attempt));
+        }
+    }
+    Ok(FetchDecision::Fetch)
+}
+
+/// Sets an integer convar, leaving its history alone if unchanged.
+fn update_int(connection: &mut PgConnection, key: &str, value: i32) -> eyre::Result<()> {
+    if convars::get_int(connection, key)? != Some(value) {
+        convars::set_int(connection, key, value)?;
+    }
+    Ok(())
+}
+
+fn has_complete_day(
+    connection: &mut PgConnection,
+    delivery: &NaiveDate,
+    area: &str,
+) -> eyre::Result<bool> {
+    let date = parse_date(&delivery.format("%Y-%m-%d").to_string(), &MARKET_TZ)?;
+    let midnight = date
+        .and_hms_opt(0, 0, 0)
+        .ok_or(eyre!("No midnight on {}", delivery))?;
+    let mut column = DateColumn::new(date);
+    column.cells = PriceCell::get_prices_from_db(connection, &midnight, area)?;
+    Ok(column.is_complete())
+}
+
+/// Fetches the prices of `delivery` unless they are already stored,
+/// not yet published, or a failed attempt is still backing off.
+///
+/// Returns whether the delivery day became complete with this call,
+/// in which case its plan should be made again.
+pub fn fetch_day(
+    connection: &mut PgConnection,
+    config: &ConfigFile,
+    delivery: &NaiveDate,
+) -> eyre::Result<bool> {
+    let now = Utc::now();
+    let failures = convars::get_int(connection, CVAR_FETCH_FAILURES)?.unwrap_or(0);
+    let last_attempt = convars::get_string(connection, CVAR_LAST_FETCH_ATTEMPT)?
+        .and_then(|v| DateTime::parse_from_rfc3339(&v).ok())
+        .map(|v| v.with_timezone(&Utc));
+    let complete = has_complete_day(connection, delivery, config.area())?;
+
+    match decide(&now, delivery, complete, failures, last_attempt)? {
+        FetchDecision::Complete => {
+            println!("Prices for {} are already complete", delivery);
+            return Ok(false);
+        }
+        FetchDecision::TooEarly(publication) => {
+            println!("Prices for {} are expected after {}", delivery, publication);
+            update_int(connection, CVAR_FETCH_FAILURES, 0)?;
+            update_int(connection, CVAR_TOMORROW_MISSING, 1)?;
+            return Ok(false);
+        }
+        FetchDecision::Backoff(next_attempt) => {
+            println!("{} failed fetches, next attempt after {}", failures, next_attempt);
+            return Ok(false);
+        }
+        FetchDecision::Fetch => (),
+    }
+
+    convars::set_string(connection, CVAR_LAST_FETCH_ATTEMPT, &now.to_rfc3339())?;
+    let sources = config.get_price_sources();
+    match price_source::fetch_from_sources(&sources, delivery, config.area()) {
+        Ok(matrix) => {
+            let validation = config.validation();
+            let planning_now = now.with_timezone(&PLANNING_TZ);
+            let revised = price_matrix::insert_matrix_to_database(
+                connection,
+                &matrix,
+                &validation,
+                &planning_now,
+            )?;
+            if revised > 0 {
+                println!("{} prices were revised", revised);
+            }
+        }
+        Err(e) => eprintln!("Fetching prices for {} failed: {}", delivery, e),
+    }
+
+    if has_complete_day(connection, delivery, config.area())? {
+        convars::set_string(connection, CVAR_LAST_SUCCESSFUL_FETCH, &now.to_rfc3339())?;
+        update_int(connection, CVAR_FETCH_FAILURES, 0)?;
+        update_int(connection, CVAR_TOMORROW_MISSING, 0)?;
+        Ok(true)
+    } else {
+        eprintln!("Prices for {} are still incomplete", delivery);
+        convars::set_int(connection, CVAR_FETCH_FAILURES, failures + 1)?;
+        update_int(connection, CVAR_TOMORROW_MISSING, 1)?;
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn delivery() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 10, 11).unwrap()
+    }
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        MARKET_TZ
+            .with_ymd_and_hms(2024, 10, 10, hour, minute, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn publishes_day_before() {
+        let publication = expected_publication(&delivery()).unwrap();
+        assert!(publication == MARKET_TZ.with_ymd_and_hms(2024, 10, 10, 12, 45, 0).unwrap());
+    }
+
+    #[test]
+    fn doubles_retry_delay() {
+        assert!(retry_delay(0) == Duration::zero());
+        assert!(retry_delay(1) == Duration::minutes(5));
+        assert!(retry_delay(3) == Duration::minutes(20));
+        assert!(retry_delay(5) == Duration::hours(1));
+        assert!(retry_delay(100) == Duration::hours(1));
+    }
+
+    #[test]
+    fn waits_for_publication() {
+        let decision = decide(&at(11, 0), &delivery(), false, 0, None).unwrap();
+        assert!(matches!(decision, FetchDecision::TooEarly(_)));
+        let decision = decide(&at(13, 0), &delivery(), false, 0, None).unwrap();
+        assert!(decision == FetchDecision::Fetch);
+        let decision = decide(&at(13, 0), &delivery(), true, 0, None).unwrap();
+        assert!(decision == FetchDecision::Complete);
+    }
+
+    #[test]
+    fn backs_off_after_failures() {
+        let last = Some(at(13, 0));
+        let decision = decide(&at(13, 15), &delivery(), false, 3, last).unwrap();
+        assert!(decision == FetchDecision::Backoff(at(13, 20)));
+        let decision = decide(&at(13, 20), &delivery(), false, 3, last).unwrap();
+        assert!(decision == FetchDecision::Fetch);
+    }
+}
@@ -1,192 +1,385 @@
-use bdays::easter::{easter_naive_date, EasterError};
-use chrono::{Date, Datelike, Duration};
-use chrono_tz::Tz;
-use lazy_static::lazy_static;
+use bdays::easter::easter_naive_date;
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use serde::Deserialize;
 
-use crate::constants::MARKET_TZ;
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub enum Country {
+    #[default]
+    EE,
+    FI,
+    LV,
+    LT,
+}
 
 struct MonthDay {
     month: u32,
     day: u32,
 }
 
-// month-day
-const FIXED_NATL_HOLIDAYS: [MonthDay; 8] = [
+const fn md(month: u32, day: u32) -> MonthDay {
+    MonthDay { month, day }
+}
+
+const EE_FIXED: [MonthDay; 9] = [
     // uusaasta
-    MonthDay { month: 1, day: 1 },
+    md(1, 1),
+    // iseseisvuspäev
+    md(2, 24),
     // kevadpüha
-    MonthDay { month: 5, day: 1 },
+    md(5, 1),
     // võidupüha
-    MonthDay { month: 6, day: 23 },
+    md(6, 23),
     // jaanipäev
-    MonthDay { month: 6, day: 24 },
+    md(6, 24),
     // taasiseseisvumispäev
-    MonthDay { month: 8, day: 20 },
+    md(8, 20),
     // jõululaupäev
-    MonthDay { month: 12, day: 24 },
-    // 1. jõulupäuha
-    MonthDay { month: 12, day: 25 },
-    // 2. jõulupäuha
-    MonthDay { month: 12, day: 26 },
+    md(12, 24),
+    // 1. jõulupüha
+    md(12, 25),
+    // 2. jõulupüha
+    md(12, 26),
 ];
 
-fn precompute_easter_dates() -> Result<[MonthDay; 6], EasterError> {
-    let now = chrono::Local::now().with_timezone(&MARKET_TZ);
-    let e1 = easter_naive_date(now.year())?;
-    let e2 = easter_naive_date(now.year() + 1)?;
-    let gf1 = e1 - Duration::days(2);
-    let gf2 = e2 - Duration::days(2);
-    let pc1 = e1 + Duration::days(49);
-    let pc2 = e2 + Duration::days(49);
-    Ok([
-        MonthDay {
-            month: e1.month(),
-            day: e1.day(),
-        },
-        MonthDay {
-            month: e2.month(),
-            day: e2.day(),
-        },
-        MonthDay {
-            month: gf1.month(),
-            day: gf1.day(),
-        },
-        MonthDay {
-            month: gf2.month(),
-            day: gf2.day(),
-        },
-        MonthDay {
-            month: pc1.month(),
-            day: pc1.day(),
-        },
-        MonthDay {
-            month: pc2.month(),
-            day: pc2.day(),
-        },
-    ])
-}
+const FI_FIXED: [MonthDay; 7] = [
+    md(1, 1),
+    md(1, 6),
+    md(5, 1),
+    md(12, 6),
+    md(12, 24),
+    md(12, 25),
+    md(12, 26),
+];
+
+const LV_FIXED: [MonthDay; 10] = [
+    md(1, 1),
+    md(5, 1),
+    md(5, 4),
+    md(6, 23),
+    md(6, 24),
+    md(11, 18),
+    md(12, 24),
+    md(12, 25),
+    md(12, 26),
+    md(12, 31),
+];
+
+const LT_FIXED: [MonthDay; 12] = [
+    md(1, 1),
+    md(2, 16),
+    md(3, 11),
+    md(5, 1),
+    md(6, 24),
+    md(7, 6),
+    md(8, 15),
+    md(11, 1),
+    md(11, 2),
+    md(12, 24),
+    md(12, 25),
+    md(12, 26),
+];
 
-lazy_static! {
-    static ref EASTER_DATES: Result<[MonthDay; 6], EasterError> = precompute_easter_dates();
+/// Days counted from Easter Sunday.
+const EE_EASTER: [i64; 3] = [-2, 0, 49];
+const FI_EASTER: [i64; 5] = [-2, 0, 1, 39, 49];
+const LV_EASTER: [i64; 4] = [-2, 0, 1, 49];
+const LT_EASTER: [i64; 2] = [0, 1];
+
+/// First day from `from` on that falls on the given weekday.
+fn weekday_on_or_after(from: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let days = (7 + weekday.num_days_from_monday() - from.weekday().num_days_from_monday()) % 7;
+    from + Duration::days(days.into())
 }
 
-fn is_same_date<D1: Datelike, D2: Datelike>(d1: &D1, d2: &D2) -> bool {
-    d1.month() == d2.month() && d1.day() == d2.day()
+impl Country {
+    fn fixed(self) -> &'static [MonthDay] {
+        match self {
+            Country::EE => &EE_FIXED,
+            Country::FI => &FI_FIXED,
+            Country::LV => &LV_FIXED,
+            Country::LT => &LT_FIXED,
+        }
+    }
+
+    fn easter_offsets(self) -> &'static [i64] {
+        match self {
+            Country::EE => &EE_EASTER,
+            Country::FI => &FI_EASTER,
+            Country::LV => &LV_EASTER,
+            Country::LT => &LT_EASTER,
+        }
+    }
+
+    /// Holidays of a year that move with the weekday.
+    fn movable(self, year: i32) -> Vec<NaiveDate> {
+        let date = |month, day| NaiveDate::from_ymd_opt(year, month, day);
+        let mut days = vec![];
+        match self {
+            Country::EE => (),
+            Country::FI => {
+                // Midsummer Eve and Day, All Saints' Day
+                if let Some(eve) = date(6, 19).map(|d| weekday_on_or_after(d, Weekday::Fri)) {
+                    days.extend([eve, eve + Duration::days(1)]);
+                }
+                days.extend(date(10, 31).map(|d| weekday_on_or_after(d, Weekday::Sat)));
+            }
+            Country::LV => {
+                // Independence days falling on a weekend move to Monday
+                for (month, day) in [(5, 4), (11, 18)] {
+                    let moved = date(month, day)
+                        .filter(|d| matches!(d.weekday(), Weekday::Sat | Weekday::Sun))
+                        .map(|d| weekday_on_or_after(d, Weekday::Mon));
+                    days.extend(moved);
+                }
+            }
+            Country::LT => {
+                // Mother's Day and Father's Day
+                days.extend(date(5, 1).map(|d| weekday_on_or_after(d, Weekday::Sun)));
+                days.extend(date(6, 1).map(|d| weekday_on_or_after(d, Weekday::Sun)));
+            }
+        }
+        days
+    }
 }
 
-fn is_fixed_national_holiday(date: &Date<Tz>) -> bool {
-    FIXED_NATL_HOLIDAYS
-        .iter()
-        .any(|hd| hd.month == date.month() && hd.day == date.day())
+/// Public holidays of a country along with custom days off,
+/// answered for any year.
+#[derive(Clone, Debug, Default)]
+pub struct HolidayCalendar {
+    pub country: Country,
+    /// Single custom days off.
+    pub extra_days: Vec<NaiveDate>,
+    /// Custom days off recurring every year on the month and
+    /// day of the given date.
+    pub yearly_days: Vec<NaiveDate>,
 }
 
-fn is_easter_or_good_friday(date: &Date<Tz>) -> bool {
-    let easter = easter_naive_date(date.year());
-    if let Ok(easter) = easter {
-        let good_friday = easter - Duration::days(2);
-        let pentecost = easter + Duration::days(49);
-        is_same_date(&easter, date)
-            || is_same_date(&good_friday, date)
-            || is_same_date(&pentecost, date)
-    } else {
-        false
+impl HolidayCalendar {
+    pub fn new(country: Country) -> HolidayCalendar {
+        HolidayCalendar { country, ..Default::default() }
     }
-}
 
-fn is_easter_related_date(date: &Date<Tz>) -> bool {
-    if let Ok(easter_holidays) = &*EASTER_DATES {
-        easter_holidays
+    fn is_public_holiday(&self, date: &NaiveDate) -> bool {
+        let fixed = self
+            .country
+            .fixed()
             .iter()
-            .any(|hd| hd.month == date.month() && hd.day == date.day())
-    } else {
-        false
+            .any(|hd| hd.month == date.month() && hd.day == date.day());
+        let easter = easter_naive_date(date.year()).is_ok_and(|easter| {
+            let offset = (*date - easter).num_days();
+            self.country.easter_offsets().contains(&offset)
+        });
+        fixed || easter || self.country.movable(date.year()).contains(date)
+    }
+
+    pub fn is_holiday(&self, date: &NaiveDate) -> bool {
+        self.is_public_holiday(date)
+            || self.extra_days.contains(date)
+            || self
+                .yearly_days
+                .iter()
+                .any(|d| d.month() == date.month() && d.day() == date.day())
+    }
+
+    /// Adds the all-day events of an iCalendar file as days off.
+    /// Events repeating yearly are added as yearly days.
+    pub fn add_ics(&mut self, body: &str) -> eyre::Result<usize> {
+        let events = ics::parse_events(body)?;
+        let count = events.len();
+        for event in events {
+            let mut day = event.start;
+            while day < event.end {
+                if event.yearly {
+                    self.yearly_days.push(day);
+                } else {
+                    self.extra_days.push(day);
+                }
+                day += Duration::days(1);
+            }
+        }
+        Ok(count)
+    }
+}
+
+/// Holiday settings in the configuration file.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct HolidayConfig {
+    /// Country of the public holidays. Defaults to `EE`.
+    pub country: Option<Country>,
+    pub extra_days: Option<Vec<NaiveDate>>,
+    /// Path of an iCalendar file of further days off.
+    pub ics_file: Option<String>,
+}
+
+impl HolidayConfig {
+    pub fn calendar(&self) -> eyre::Result<HolidayCalendar> {
+        let mut calendar = HolidayCalendar::new(self.country.unwrap_or_default());
+        calendar.extra_days = self.extra_days.clone().unwrap_or_default();
+        if let Some(filename) = &self.ics_file {
+            let body = std::fs::read_to_string(filename)
+                .map_err(|e| eyre::eyre!("Unable to read {}: {}", filename, e))?;
+            calendar.add_ics(&body)?;
+        }
+        Ok(calendar)
     }
 }
 
-pub fn is_national_holiday(date: &Date<Tz>) -> bool {
-    is_fixed_national_holiday(date)
+mod ics {
+    use chrono::{Duration, NaiveDate};
+    use eyre::eyre;
+
+    pub struct Event {
+        pub start: NaiveDate,
+        /// First day after the event.
+        pub end: NaiveDate,
+        pub yearly: bool,
+    }
+
+    /// Unfolds continuation lines, which start with a space or tab.
+    fn unfold(body: &str) -> Vec<String> {
+        let mut lines: Vec<String> = vec![];
+        for line in body.lines() {
+            match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+                (Some(rest), Some(last)) => last.push_str(rest),
+                _ => lines.push(line.trim_end().to_owned()),
+            }
+        }
+        lines
+    }
+
+    /// Date of a `DTSTART` or `DTEND` value, either a plain date
+    /// or a date and time.
+    fn parse_date(value: &str) -> eyre::Result<NaiveDate> {
+        let date = value.get(..8).ok_or(eyre!("Invalid iCalendar date: {}", value))?;
+        Ok(NaiveDate::parse_from_str(date, "%Y%m%d")?)
+    }
+
+    pub fn parse_events(body: &str) -> eyre::Result<Vec<Event>> {
+        let mut events = vec![];
+        let mut current: Option<(Option<NaiveDate>, Option<NaiveDate>, bool)> = None;
+        for line in unfold(body) {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let property = name.split(';').next().unwrap_or(name);
+            match (property, current.as_mut()) {
+                ("BEGIN", _) if value == "VEVENT" => current = Some((None, None, false)),
+                ("END", Some((start, end, yearly))) if value == "VEVENT" => {
+                    let start = start.ok_or(eyre!("iCalendar event without DTSTART"))?;
+                    let end = end.filter(|end| *end > start).unwrap_or(start + Duration::days(1));
+                    events.push(Event { start, end, yearly: *yearly });
+                    current = None;
+                }
+                ("DTSTART", Some(event)) => event.0 = Some(parse_date(value)?),
+                ("DTEND", Some(event)) => event.1 = Some(parse_date(value)?),
+                ("RRULE", Some(event)) => event.2 = value.contains("FREQ=YEARLY"),
+                _ => (),
+            }
+        }
+        Ok(events)
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use chrono::TimeZone;
-    use chrono_tz::Europe::Tallinn;
-
     use super::*;
 
+    fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn estonia() -> HolidayCalendar {
+        HolidayCalendar::new(Country::EE)
+    }
+
     #[test]
     fn checks_easter() {
-        let easter_2021 = Tallinn.ymd(2021, 4, 4);
-        assert!(is_easter_or_good_friday(&easter_2021));
-        let not_easter_2021 = Tallinn.ymd(2021, 4, 5);
-        assert!(!is_easter_or_good_friday(&not_easter_2021));
-        let easter_2022 = Tallinn.ymd(2022, 4, 17);
-        assert!(is_easter_or_good_friday(&easter_2022));
-        let not_easter_2022 = Tallinn.ymd(2022, 4, 16);
-        assert!(!is_easter_or_good_friday(&not_easter_2022));
-        let easter_2023 = Tallinn.ymd(2023, 4, 9);
-        assert!(is_easter_or_good_friday(&easter_2023));
-        let easter_2024 = Tallinn.ymd(2024, 3, 31);
-        assert!(is_easter_or_good_friday(&easter_2024));
+        assert!(estonia().is_holiday(&ymd(2021, 4, 4)));
+        assert!(!estonia().is_holiday(&ymd(2021, 4, 5)));
+        assert!(estonia().is_holiday(&ymd(2022, 4, 17)));
+        assert!(!estonia().is_holiday(&ymd(2022, 4, 16)));
+        assert!(estonia().is_holiday(&ymd(2023, 4, 9)));
+        assert!(estonia().is_holiday(&ymd(2024, 3, 31)));
+        assert!(estonia().is_holiday(&ymd(1995, 4, 16)));
+        assert!(estonia().is_holiday(&ymd(2050, 4, 10)));
     }
 
     #[test]
     fn checks_good_friday() {
-        let good_friday_2021 = Tallinn.ymd(2021, 4, 2);
-        assert!(is_easter_or_good_friday(&good_friday_2021));
-        let not_good_friday_2021 = Tallinn.ymd(2021, 4, 1);
-        assert!(!is_easter_or_good_friday(&not_good_friday_2021));
-        let good_friday_2022 = Tallinn.ymd(2022, 4, 15);
-        assert!(is_easter_or_good_friday(&good_friday_2022));
-        let not_good_friday_2022 = Tallinn.ymd(2022, 4, 16);
-        assert!(!is_easter_or_good_friday(&not_good_friday_2022));
-        let good_friday_2023 = Tallinn.ymd(2023, 4, 7);
-        assert!(is_easter_or_good_friday(&good_friday_2023));
-        let good_friday_2024 = Tallinn.ymd(2024, 3, 29);
-        assert!(is_easter_or_good_friday(&good_friday_2024));
+        assert!(estonia().is_holiday(&ymd(2021, 4, 2)));
+        assert!(!estonia().is_holiday(&ymd(2021, 4, 1)));
+        assert!(estonia().is_holiday(&ymd(2022, 4, 15)));
+        assert!(estonia().is_holiday(&ymd(2023, 4, 7)));
+        assert!(estonia().is_holiday(&ymd(2024, 3, 29)));
     }
 
     #[test]
     fn checks_pentecost() {
-        let pentecost_2021 = Tallinn.ymd(2021, 5, 23);
-        assert!(is_easter_or_good_friday(&pentecost_2021));
-        let not_pentecost_2021 = Tallinn.ymd(2021, 5, 24);
-        assert!(!is_easter_or_good_friday(&not_pentecost_2021));
-        let pentecost_2022 = Tallinn.ymd(2022, 6, 5);
-        assert!(is_easter_or_good_friday(&pentecost_2022));
-        let not_pentecost_2022 = Tallinn.ymd(2022, 6, 12);
-        assert!(!is_easter_or_good_friday(&not_pentecost_2022));
-        let pentecost_2023 = Tallinn.ymd(2023, 5, 28);
-        assert!(is_easter_or_good_friday(&pentecost_2023));
-        let not_pentecost_2023 = Tallinn.ymd(2023, 5, 29);
-        assert!(!is_easter_or_good_friday(&not_pentecost_2023));
-        let pentecost_2024 = Tallinn.ymd(2024, 5, 19);
-        assert!(is_easter_or_good_friday(&pentecost_2024));
-        let not_pentecost_2024 = Tallinn.ymd(2024, 5, 20);
-        assert!(!is_easter_or_good_friday(&not_pentecost_2024));
+        assert!(estonia().is_holiday(&ymd(2021, 5, 23)));
+        assert!(!estonia().is_holiday(&ymd(2021, 5, 24)));
+        assert!(estonia().is_holiday(&ymd(2022, 6, 5)));
+        assert!(!estonia().is_holiday(&ymd(2022, 6, 12)));
+        assert!(estonia().is_holiday(&ymd(2023, 5, 28)));
+        assert!(estonia().is_holiday(&ymd(2024, 5, 19)));
+        assert!(!estonia().is_holiday(&ymd(2024, 5, 20)));
     }
 
     #[test]
     fn checks_fixed_holidays() {
-        let new_years = Tallinn.ymd(2023, 1, 1);
-        assert!(is_national_holiday(&new_years));
-        let may_day = Tallinn.ymd(2024, 5, 1);
-        assert!(is_national_holiday(&may_day));
-        let v_day = Tallinn.ymd(2025, 6, 23);
-        assert!(is_national_holiday(&v_day));
-        let j_day = Tallinn.ymd(2026, 6, 24);
-        assert!(is_national_holiday(&j_day));
-        let re_day = Tallinn.ymd(2027, 8, 20);
-        assert!(is_national_holiday(&re_day));
-        let christmas = Tallinn.ymd(2028, 12, 24);
-        assert!(is_national_holiday(&christmas));
-        let christmas_1 = Tallinn.ymd(2029, 12, 25);
-        assert!(is_national_holiday(&christmas_1));
-        let christmas_2 = Tallinn.ymd(2030, 12, 26);
-        assert!(is_national_holiday(&christmas_2));
-        let old_years = Tallinn.ymd(2023, 12, 31);
-        assert!(!is_national_holiday(&old_years));
-        let september_1 = Tallinn.ymd(2020, 9, 1);
-        assert!(!is_national_holiday(&september_1));
+        assert!(estonia().is_holiday(&ymd(2023, 1, 1)));
+        assert!(estonia().is_holiday(&ymd(2024, 2, 24)));
+        assert!(estonia().is_holiday(&ymd(2024, 5, 1)));
+        assert!(estonia().is_holiday(&ymd(2025, 6, 23)));
+        assert!(estonia().is_holiday(&ymd(2026, 6, 24)));
+        assert!(estonia().is_holiday(&ymd(2027, 8, 20)));
+        assert!(estonia().is_holiday(&ymd(2028, 12, 24)));
+        assert!(estonia().is_holiday(&ymd(2029, 12, 25)));
+        assert!(estonia().is_holiday(&ymd(2030, 12, 26)));
+        assert!(!estonia().is_holiday(&ymd(2023, 12, 31)));
+        assert!(!estonia().is_holiday(&ymd(2020, 9, 1)));
+    }
+
+    #[test]
+    fn checks_other_countries() {
+        let finland = HolidayCalendar::new(Country::FI);
+        // Midsummer Eve, All Saints' Day, Ascension, St Stephen's Day
+        assert!(finland.is_holiday(&ymd(2024, 6, 21)));
+        assert!(finland.is_holiday(&ymd(2024, 11, 2)));
+        assert!(finland.is_holiday(&ymd(2024, 5, 9)));
+        assert!(finland.is_holiday(&ymd(2024, 12, 26)));
+        assert!(!finland.is_holiday(&ymd(2024, 2, 24)));
+
+        let latvia = HolidayCalendar::new(Country::LV);
+        assert!(latvia.is_holiday(&ymd(2024, 12, 31)));
+        // 18 November 2023 was a Saturday
+        assert!(latvia.is_holiday(&ymd(2023, 11, 20)));
+        assert!(!latvia.is_holiday(&ymd(2024, 11, 19)));
+
+        let lithuania = HolidayCalendar::new(Country::LT);
+        assert!(lithuania.is_holiday(&ymd(2024, 3, 11)));
+        assert!(lithuania.is_holiday(&ymd(2024, 4, 1)));
+        assert!(!lithuania.is_holiday(&ymd(2024, 3, 29)));
+    }
+
+    #[test]
+    fn reads_ics_days() {
+        let body = "BEGIN:VCALENDAR\r\n\
+            BEGIN:VEVENT\r\n\
+            SUMMARY:Company days off\r\n\
+            DTSTART;VALUE=DATE:20241227\r\n\
+            DTEND;VALUE=DATE:20241231\r\n\
+            END:VEVENT\r\n\
+            BEGIN:VEVENT\r\n\
+            SUMMARY:Birthday\r\n\
+            DTSTART:20200305T000000Z\r\n\
+            RRULE:FREQ=YEARLY\r\n\
+            END:VEVENT\r\n\
+            END:VCALENDAR\r\n";
+        let mut calendar = estonia();
+        assert!(calendar.add_ics(body).unwrap() == 2);
+        assert!(calendar.is_holiday(&ymd(2024, 12, 27)));
+        assert!(calendar.is_holiday(&ymd(2024, 12, 30)));
+        assert!(!calendar.is_holiday(&ymd(2024, 12, 31)));
+        assert!(calendar.is_holiday(&ymd(2031, 3, 5)));
     }
 }
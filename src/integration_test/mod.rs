@@ -44,13 +44,13 @@ mod tests {
         assert!(cfdb.is_some());
         let cfdb_id = cfdb.unwrap().id;
 
-        let pdb = PriceCell::get_prices_from_db(&connection, &start_date).unwrap();
+        let pdb = PriceCell::get_prices_from_db(&connection, &start_date, config.area()).unwrap();
         let config_today = config.get_day(&start_date.weekday());
 
-        let base = config_today.base.unwrap_or(DayBasePlan::Tariff(TariffStrategy));
-        let base_prices = base.get_hour_strategy().plan_day_full(&pdb, &start_date).unwrap();
+        let base = config_today.base.clone().unwrap_or(DayBasePlan::Tariff(TariffStrategy::default()));
+        let base_prices = base.get_hour_strategy(&config.tariff_package().unwrap()).plan_day_full(&pdb, &start_date, config.resolution()).unwrap();
 
-        let mut strategy_result = match config_today.strategy {
+        let mut strategy_result = match config_today.strategy.clone() {
             Some(strategy) => strategy.get_day_strategy().plan_day_masked(&base_prices),
             None => base_prices,
         };
@@ -1,72 +1,214 @@
 #[macro_use]
 extern crate diesel;
 mod apply;
+mod cheap_power;
 // mod bar_chart;
 mod config_file;
 mod constants;
 mod convars;
 mod database;
+mod entsoe;
+mod fetch_schedule;
 mod holidays;
 // mod nord_pool_spot;
+mod nord_pool_data_portal;
 mod nord_pool_meta;
 mod nord_pool_spot_json;
 mod overrides;
 mod price_cell;
+mod price_components;
+mod price_estimate;
+mod price_import;
 mod price_matrix;
+mod price_source;
+mod price_validation;
 mod proc_mutex;
 mod sample_data;
 mod schema;
 mod strategy;
 mod switch_records;
 mod tariff;
+mod tariff_price;
 
 use std::{io::Write, ops::Add, process::exit};
 
-use chrono::{DateTime, Datelike, Timelike, Utc};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
 use chrono_tz::Tz;
-use config_file::ConfigFile;
-use constants::{DEFAULT_CONFIG_FILENAME, LOCAL_TZ, PLANNING_TZ};
+use config_file::{ConfigFile, Day};
+use diesel::PgConnection;
+use constants::{DEFAULT_CONFIG_FILENAME, LOCAL_TZ, MARKET_TZ, PLANNING_TZ};
 use eyre::eyre;
 
-use price_cell::get_hour_start_end;
 use proc_mutex::wait_for_file;
-use strategy::{power_state_model::PowerStateDB, PowerState, PriceChangeUnit};
+use strategy::{constraints, power_state_model::PowerStateDB, PowerState, PriceChangeUnit};
 
 use crate::{
-    apply::apply_power_state, config_file::DayBasePlan, price_cell::PriceCell,
+    apply::apply_power_state,
+    config_file::DayBasePlan,
+    nord_pool_meta::convert_price_to_decimal,
+    price_cell::PriceCell,
+    price_matrix::{CentsPerKwh, DaySlice},
     strategy::default::TariffStrategy,
+    tariff_price::{TariffRate, TariffTable},
 };
 
-fn fetch_main() -> eyre::Result<()> {
+/// Fetches tomorrow's prices if they are due, returning whether
+/// tomorrow has to be planned again.
+fn fetch_main() -> eyre::Result<bool> {
     let mut connection = database::establish_connection();
+    let (_, config) =
+        ConfigFile::fetch_with_default_inserting(&mut connection, DEFAULT_CONFIG_FILENAME)?;
 
-    let date_matrix = nord_pool_spot_json::fetch_json_from_nord_pool()?;
-    price_matrix::insert_matrix_to_database(&mut connection, &date_matrix)?;
-
-    Ok(())
+    let tomorrow = Utc::now().with_timezone(&MARKET_TZ).date_naive() + chrono::Duration::days(1);
+    fetch_schedule::fetch_day(&mut connection, &config, &tomorrow)
 }
 
 fn get_power_state_exact(
     datetime: &DateTime<Tz>,
     states: &Vec<PriceChangeUnit>,
 ) -> eyre::Result<PowerState> {
-    let range = get_hour_start_end(datetime)?;
     for pcu in states {
-        let moment = pcu
-            .moment
-            .with_minute(1)
-            .ok_or(eyre!("Unable to set 1 minute"))?;
-        // println!("trying on {}", moment);
-        if range.contains(&moment) {
-            println!("Range found: {}: {:?}", moment, range);
+        if pcu.contains(datetime) {
+            println!("Range found: {} + {}", pcu.moment, pcu.interval);
             return Ok(pcu.state);
-        } else {
-            // println!("Range doesn't contain {:?}: {:?}", pcu.moment, range);
         }
     }
     Err(eyre!("Range not found"))
 }
 
+/// Prices of the day to plan: stored and, if enabled, estimated
+/// prices in the planning resolution, with the configured tariff
+/// and price components attached.
+fn load_day_prices(
+    connection: &mut PgConnection,
+    config: &ConfigFile,
+    date: &DateTime<Tz>,
+) -> eyre::Result<DaySlice> {
+    let pdb = PriceCell::get_prices_from_db(connection, date, config.area())?;
+    let pdb = if config.estimate_prices.unwrap_or(false) {
+        let (day_start, day_end) = price_cell::get_day_start_end(date)?;
+        let area = config.area();
+        price_estimate::estimate_missing(connection, &pdb, &day_start, &day_end, area)?
+    } else {
+        pdb
+    };
+    let mut pdb = price_matrix::expand_to_interval(&pdb, config.resolution());
+    let package = config.tariff_package()?;
+    let tariffs = TariffTable::load(connection)?;
+    for cell in pdb.0.iter_mut() {
+        cell.attach_tariff(&package, &tariffs);
+        cell.attach_components(config.price_components());
+    }
+    Ok(pdb)
+}
+
+/// Prints the consumer price breakdown of every interval of the day.
+fn breakdown_main(date: DateTime<Tz>) -> eyre::Result<()> {
+    let mut connection = database::establish_connection();
+    let (_, config) =
+        ConfigFile::fetch_with_default_inserting(&mut connection, DEFAULT_CONFIG_FILENAME)?;
+    let prices = load_day_prices(&mut connection, &config, &date)?;
+    for cell in &prices.0 {
+        if let Some(breakdown) = &cell.breakdown {
+            let estimate = if cell.estimated { " (estimate)" } else { "" };
+            println!("{}{}: {}", cell.moment.with_timezone(&LOCAL_TZ), estimate, breakdown);
+        }
+    }
+    Ok(())
+}
+
+/// Prints the stored state of every interval of the day with the
+/// reasons it was planned that way, the latest plan of each.
+fn explain_main(date: DateTime<Tz>) -> eyre::Result<()> {
+    let mut connection = database::establish_connection();
+    let (conf_id, _) =
+        ConfigFile::fetch_with_default_inserting(&mut connection, DEFAULT_CONFIG_FILENAME)?;
+    let states = PowerStateDB::get_day_from_database(&mut connection, &date, Some(conf_id))?;
+    for pcu in PowerStateDB::latest_states(&states) {
+        println!("{} {:?}", pcu.moment.with_timezone(&LOCAL_TZ), pcu.state);
+        for reason in &pcu.reasons {
+            println!("    {}", reason);
+        }
+    }
+    Ok(())
+}
+
+/// How long power had been off when the day of `date` started,
+/// if the day's plan depends on it.
+fn carried_off(
+    connection: &mut PgConnection,
+    config_day: &Day,
+    date: &DateTime<Tz>,
+    conf_id: i32,
+) -> eyre::Result<chrono::Duration> {
+    if !config_day.needs_carried_off() {
+        return Ok(chrono::Duration::zero());
+    }
+    let (day_start, _) = price_cell::get_day_start_end(date)?;
+    let yesterday = *date - chrono::Duration::days(1);
+    let yesterday_states =
+        PowerStateDB::get_day_from_database(connection, &yesterday, Some(conf_id))?;
+    Ok(constraints::trailing_off(&yesterday_states, &day_start))
+}
+
+/// The base plan of a day with every strategy step of the day applied.
+fn plan_day_steps<'a>(
+    config: &ConfigFile,
+    date: &DateTime<Tz>,
+    prices: &'a DaySlice,
+    carried_off: chrono::Duration,
+) -> eyre::Result<Vec<PriceChangeUnit<'a>>> {
+    let config_day = config.get_day(&date.weekday());
+    let package = config.tariff_package()?;
+
+    let base = config_day
+        .base
+        .clone()
+        .unwrap_or(DayBasePlan::Tariff(TariffStrategy::default()));
+    let base_plan = base
+        .get_hour_strategy(&package)
+        .plan_day_full(prices, date, config.resolution())?;
+    Ok(config_day.plan_steps(config, base_plan, carried_off))
+}
+
+/// Turns power on in cheap hours, applies the overrides and
+/// constraints of the day to a plan and stores it. Prints the
+/// plan and, with the device power known, what it will cost.
+fn finish_day(
+    connection: &mut PgConnection,
+    config: &ConfigFile,
+    date: &DateTime<Tz>,
+    conf_id: i32,
+    strategy_result: &mut [PriceChangeUnit],
+) -> eyre::Result<()> {
+    let config_day = config.get_day(&date.weekday());
+    if let Some(cheap_power) = &config.cheap_power {
+        cheap_power.force_on(strategy_result);
+    }
+    overrides::apply_overrides(strategy_result, config, &LOCAL_TZ);
+
+    if let Some(constraints) = &config_day.constraints {
+        let carried_off = carried_off(connection, config_day, date, conf_id)?;
+        match &config.cheap_power {
+            Some(cheap_power) => {
+                cheap_power.apply_constraints(strategy_result, constraints, carried_off)
+            }
+            None => constraints.apply(strategy_result, carried_off),
+        }
+    }
+
+    PowerStateDB::insert_day_into_database(connection, strategy_result, Some(conf_id));
+    for pcu in strategy_result.iter() {
+        println!("{:?}", pcu);
+    }
+    if let Some(power_kw) = config.power_kw {
+        let basis = config.cost_basis(config_day);
+        let cost = strategy::projected_cost(strategy_result, power_kw, basis);
+        println!("Projected cost of {}: {} €", date.date_naive(), cost.round_dp(2));
+    }
+    Ok(())
+}
+
 fn planner_main(force_recalculate: bool, moment: DateTime<Tz>) -> eyre::Result<()> {
     let mut connection = database::establish_connection();
     let (conf_id, config) =
@@ -94,26 +236,123 @@ fn planner_main(force_recalculate: bool, moment: DateTime<Tz>) -> eyre::Result<(
     let config_day = config.get_day(&date.weekday());
     println!("{:?}", config_day);
 
-    let pdb = PriceCell::get_prices_from_db(&mut connection, &date)?;
-
-    let base = config_day
-        .base
-        .unwrap_or(DayBasePlan::Tariff(TariffStrategy));
-    let base_prices = base.get_hour_strategy().plan_day_full(&pdb, &date)?;
+    let pdb = load_day_prices(&mut connection, &config, &date)?;
+    let carried_off = carried_off(&mut connection, config_day, &date, conf_id)?;
+    let mut strategy_result = plan_day_steps(&config, &date, &pdb, carried_off)?;
+    finish_day(&mut connection, &config, &date, conf_id, &mut strategy_result)
+}
 
-    let mut strategy_result = match config_day.strategy {
-        Some(strategy) => strategy.get_day_strategy().plan_day_masked(&base_prices),
-        None => base_prices,
+/// Plans today and tomorrow together over the configured horizon,
+/// moving on-time across midnight to where it is cheaper. Returns
+/// false without planning if no horizon is configured or tomorrow
+/// has no published prices yet.
+fn horizon_main(now: DateTime<Tz>, tomorrow: DateTime<Tz>) -> eyre::Result<bool> {
+    let mut connection = database::establish_connection();
+    let (conf_id, config) =
+        ConfigFile::fetch_with_default_inserting(&mut connection, DEFAULT_CONFIG_FILENAME)?;
+    let Some(horizon) = config.horizon else {
+        return Ok(false);
     };
 
-    overrides::apply_overrides(&mut strategy_result, &config, &LOCAL_TZ);
-
-    PowerStateDB::insert_day_into_database(&mut connection, &strategy_result, Some(conf_id));
-    for pcu in &strategy_result {
-        println!("{:?}", pcu);
+    let today_prices = load_day_prices(&mut connection, &config, &now)?;
+    let tomorrow_prices = load_day_prices(&mut connection, &config, &tomorrow)?;
+    if tomorrow_prices.0.iter().all(|cell| cell.estimated) {
+        println!("No prices for tomorrow yet, planning days apart.");
+        return Ok(false);
+    }
+    println!("Planning both days until {}", now + horizon.length());
+
+    let today_day = config.get_day(&now.weekday());
+    let today_off = carried_off(&mut connection, today_day, &now, conf_id)?;
+    let mut today = plan_day_steps(&config, &now, &today_prices, today_off)?;
+
+    let tomorrow_day = config.get_day(&tomorrow.weekday());
+    if !today_day.may_rebalance() || !tomorrow_day.may_rebalance() {
+        println!("Only days planned by Smart or a daily Energy target trade on-time.");
+        // Tomorrow counts the time off of today as stored
+        finish_day(&mut connection, &config, &now, conf_id, &mut today)?;
+        let tomorrow_off = carried_off(&mut connection, tomorrow_day, &tomorrow, conf_id)?;
+        let mut tomorrow_plan =
+            plan_day_steps(&config, &tomorrow, &tomorrow_prices, tomorrow_off)?;
+        finish_day(&mut connection, &config, &tomorrow, conf_id, &mut tomorrow_plan)?;
+        return Ok(true);
     }
 
-    Ok(())
+    // Budget only days have no Constraints step, so tomorrow's
+    // steps don't depend on the time off carried over from today.
+    let mut tomorrow_plan =
+        plan_day_steps(&config, &tomorrow, &tomorrow_prices, chrono::Duration::zero())?;
+    let (tomorrow_start, _) = price_cell::get_day_start_end(&tomorrow)?;
+    let (today_steps, tomorrow_steps) = (today_day.steps(), tomorrow_day.steps());
+    let shiftable = |pcu: &PriceChangeUnit| {
+        let steps = match pcu.moment < tomorrow_start {
+            true => &today_steps,
+            false => &tomorrow_steps,
+        };
+        steps.iter().all(|step| step.may_shift(pcu))
+    };
+    strategy::horizon::rebalance(
+        &mut today,
+        &mut tomorrow_plan,
+        &now,
+        &(now + horizon.length()),
+        horizon.max_shift(),
+        horizon.price_basis.unwrap_or_default(),
+        shiftable,
+    );
+
+    finish_day(&mut connection, &config, &now, conf_id, &mut today)?;
+    finish_day(&mut connection, &config, &tomorrow, conf_id, &mut tomorrow_plan)?;
+    Ok(true)
+}
+
+/// Adds the band prices of the configured tariff package given on
+/// the command line, or lists the tariff table if none are given.
+/// Returns whether any were added.
+fn add_tariff_main(connection: &mut PgConnection) -> eyre::Result<bool> {
+    let (_, config) =
+        ConfigFile::fetch_with_default_inserting(connection, DEFAULT_CONFIG_FILENAME)?;
+    let package = config.tariff_package()?;
+    let args: Vec<String> = std::env::args().skip(2).filter(|v| !v.starts_with("--")).collect();
+    let (date, prices) = match args.split_first() {
+        Some((date, prices)) if !prices.is_empty() => (date, prices),
+        None => {
+            for rate in TariffTable::load(connection)?.0 {
+                println!(
+                    "From {}: {} {} {} c/kWh",
+                    rate.valid_from.with_timezone(&LOCAL_TZ),
+                    rate.package,
+                    rate.band,
+                    rate.price.0
+                );
+            }
+            return Ok(false);
+        }
+        _ => {
+            eprintln!("\nUsage: add-tariff FROM_DATE BAND=CENTS_KWH...\n");
+            exit(1)
+        }
+    };
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")?;
+    let valid_from = tariff_price::period_start(&date)?;
+    let mut rates = vec![];
+    for price in prices {
+        let (band, cents) = price
+            .split_once('=')
+            .ok_or(eyre!("Expected BAND=CENTS_KWH: {}", price))?;
+        if !package.bands.iter().any(|b| b.name == band) {
+            return Err(eyre!("Package {} has no band {}", package.name, band));
+        }
+        rates.push(TariffRate {
+            valid_from,
+            package: package.name.clone(),
+            band: band.to_owned(),
+            price: CentsPerKwh(convert_price_to_decimal(cents)?),
+        });
+    }
+    let added = tariff_price::add_rates(connection, &rates)?;
+    println!("Added {} {} tariff prices from {}", added, package.name, date);
+    Ok(true)
 }
 
 fn enact_now(now: DateTime<Tz>) -> eyre::Result<()> {
@@ -126,6 +365,28 @@ fn enact_now(now: DateTime<Tz>) -> eyre::Result<()> {
     Ok(())
 }
 
+/// Switches every secondary load by whether the current hour is
+/// one of its cheap hours, or only prints its state on a dry run.
+fn secondary_main(now: DateTime<Tz>, enact: bool) -> eyre::Result<()> {
+    let mut connection = database::establish_connection();
+    let (_, config) =
+        ConfigFile::fetch_with_default_inserting(&mut connection, DEFAULT_CONFIG_FILENAME)?;
+    let Some(cheap_power) = &config.cheap_power else {
+        return Ok(());
+    };
+    let prices = load_day_prices(&mut connection, &config, &now)?;
+    for load in cheap_power.secondary_loads() {
+        let state = cheap_power.secondary_state(load, &prices, &now);
+        println!("Secondary load {}: {:?}", load.name, state);
+        if enact {
+            if let Err(e) = apply::apply_secondary_state(&load.name, &state) {
+                eprintln!("Unable to switch secondary load {}: {}", load.name, e);
+            }
+        }
+    }
+    Ok(())
+}
+
 // #[tokio::main]
 // #[doc(hidden)]
 fn main() -> eyre::Result<()> {
@@ -146,7 +407,11 @@ fn main() -> eyre::Result<()> {
             eprintln!("  fetch");
             eprintln!("  hour");
             eprintln!("  hour-force");
-            eprintln!("  reinsert-config [FILENAME]\n");
+            eprintln!("  reinsert-config [FILENAME]");
+            eprintln!("  import-prices FILENAME [AREA]");
+            eprintln!("  add-tariff [FROM_DATE BAND=CENTS_KWH...]");
+            eprintln!("  breakdown [DATE]");
+            eprintln!("  explain [DATE]\n");
             exit(1)
         }
     };
@@ -160,10 +425,10 @@ fn main() -> eyre::Result<()> {
     println!("Tomorrow {}", tomorrow);
 
     let mut force_recalculate = false;
+    let mut replan_tomorrow = false;
 
     if second == "fetch" {
-        fetch_main()?;
-        force_recalculate = true;
+        replan_tomorrow = fetch_main()?;
     } else if second == "hour" {
         // Does nothing special in the condition itself.
         // Do not delete
@@ -180,20 +445,74 @@ fn main() -> eyre::Result<()> {
         let default_toml = std::fs::read_to_string(filename)?;
         ConfigFile::insert_string(&mut connection, &default_toml)?;
         force_recalculate = true;
+    } else if second == "import-prices" {
+        let filename = match std::env::args().nth(2) {
+            Some(v) => v,
+            None => {
+                eprintln!("\nPlease specify a CSV, JSON or XML file of prices to import.\n");
+                exit(1)
+            }
+        };
+        let mut connection = database::establish_connection();
+        let (_, config) =
+            ConfigFile::fetch_with_default_inserting(&mut connection, DEFAULT_CONFIG_FILENAME)?;
+        let area = std::env::args()
+            .nth(3)
+            .filter(|v| !v.starts_with("--"))
+            .unwrap_or(config.area().to_owned());
+        println!("Importing prices for {}: {}", area, filename);
+        let count = price_import::import_file(
+            &mut connection,
+            &filename,
+            &area,
+            &config.validation(),
+            &now,
+        )?;
+        println!("Read {} price cells", count);
+        force_recalculate = true;
+    } else if second == "add-tariff" {
+        let mut connection = database::establish_connection();
+        if add_tariff_main(&mut connection)? {
+            force_recalculate = true;
+        } else {
+            exit(0)
+        }
+    } else if second == "breakdown" || second == "explain" {
+        let date = match std::env::args().nth(2).filter(|v| !v.starts_with("--")) {
+            Some(v) => NaiveDate::parse_from_str(&v, "%Y-%m-%d")?
+                .and_hms_opt(12, 0, 0)
+                .and_then(|dt| dt.and_local_timezone(PLANNING_TZ).earliest())
+                .ok_or(eyre!("No noon on {}", v))?,
+            None => now,
+        };
+        if second == "explain" {
+            explain_main(date)?;
+        } else {
+            breakdown_main(date)?;
+        }
+        exit(0)
     } else {
         // let a = nord_pool_spot_json::fetch_json_from_nord_pool().await?;
         eprintln!("Unknown mode: {}", second);
         exit(1)
     }
 
-    planner_main(force_recalculate, now)?;
-    planner_main(force_recalculate, tomorrow)?;
+    let planned_horizon = (force_recalculate || replan_tomorrow) && horizon_main(now, tomorrow)?;
+    if !planned_horizon {
+        planner_main(force_recalculate, now)?;
+        planner_main(force_recalculate || replan_tomorrow, tomorrow)?;
+    }
 
     let enact = std::env::args().any(|v| &v == "--enact");
 
     if enact {
         enact_now(now)?;
-    } else {
+    }
+    // Secondary loads never keep the main load from being switched
+    if let Err(e) = secondary_main(now, enact) {
+        eprintln!("Unable to plan secondary loads: {}", e);
+    }
+    if !enact {
         println!("\nDry run complete. Specify --enact to toggle power.");
     }
 
@@ -0,0 +1,201 @@
+use chrono::{DateTime, Duration, Timelike, Utc};
+use eyre::eyre;
+use json::JsonValue;
+use std::{collections::BTreeMap, env};
+
+use crate::{
+    constants::MARKET_TZ,
+    nord_pool_meta::{convert_price_to_decimal, parse_date},
+    price_cell::PriceCell,
+    price_matrix::{DateColumn, PriceMatrix, PricePerMwh},
+    price_source::PriceSource,
+};
+
+/// Parses a `deliveryStart`/`deliveryEnd` timestamp from the Data Portal,
+/// which are always given in UTC, e.g. `2024-10-09T22:00:00Z`.
+fn parse_delivery_time(value: &JsonValue, field: &str) -> eyre::Result<DateTime<Utc>> {
+    let text = value[field]
+        .as_str()
+        .ok_or(eyre!("Missing {} in multiAreaEntries", field))?;
+    Ok(DateTime::parse_from_rfc3339(text)?.with_timezone(&Utc))
+}
+
+/// Decodes the body of a Data Portal `DayAheadPrices` response into a
+/// `PriceMatrix`, keeping only the prices of the given delivery area.
+///
+/// Entries are grouped into `DateColumn`s by their delivery date in the
+/// market timezone.
+pub fn decode_json(body: &str, area: &str) -> eyre::Result<PriceMatrix> {
+    let json = json::parse(body)?;
+
+    let mut date_map: BTreeMap<String, DateColumn> = BTreeMap::new();
+
+    let entries = &json["multiAreaEntries"];
+    let entries = match entries {
+        JsonValue::Array(vec) => vec,
+        _ => return Err(eyre!("Missing multiAreaEntries")),
+    };
+
+    for entry in entries {
+        let start = parse_delivery_time(entry, "deliveryStart")?;
+        let end = parse_delivery_time(entry, "deliveryEnd")?;
+        let interval = end - start;
+        let minutes = interval.num_minutes();
+        if minutes <= 0 || 60 % minutes != 0 {
+            return Err(eyre!("Unsupported delivery period {} - {}", start, end));
+        }
+
+        let price = &entry["entryPerArea"][area];
+        if !price.is_number() {
+            return Err(eyre!("Missing price for area {} at {}", area, start));
+        }
+        let price = convert_price_to_decimal(&price.dump())?;
+
+        let moment = start.with_timezone(&MARKET_TZ);
+        let dateline = moment.format("%Y-%m-%d").to_string();
+        if !date_map.contains_key(&dateline) {
+            let formal_date = parse_date(&dateline, &MARKET_TZ)?;
+            date_map.insert(dateline.clone(), DateColumn::new(formal_date));
+        }
+
+        let column = date_map.get_mut(&dateline).unwrap();
+        column.cells.0.push(PriceCell {
+            price: PricePerMwh(price),
+            moment,
+            tariff_price: None,
+            market_hour: moment.hour(),
+            interval,
+            source: None,
+            area: area.to_owned(),
+            estimated: false,
+            breakdown: None,
+        });
+    }
+
+    let map_to_vec = date_map.into_values().map(Some).collect();
+    Ok(map_to_vec)
+}
+
+fn fetch_delivery_date(uri: &str, date: &str, area: &str) -> eyre::Result<PriceMatrix> {
+    let response = ureq::get(uri)
+        .query("date", date)
+        .query("market", "DayAhead")
+        .query("deliveryArea", area)
+        .query("currency", "EUR")
+        .call()?;
+
+    // The Data Portal answers 204 No Content for days not yet published.
+    if response.status() == 204 {
+        return Ok(vec![]);
+    }
+
+    let body = response.into_string()?;
+    decode_json(&body, area)
+}
+
+/// Fetches today's and tomorrow's day-ahead prices of a delivery area
+/// from the Nord Pool Data Portal found at `DATA_PORTAL_URI`.
+pub fn fetch_json_from_data_portal(area: &str) -> eyre::Result<PriceMatrix> {
+    let uri = env::var("DATA_PORTAL_URI")?;
+    let today = Utc::now().with_timezone(&MARKET_TZ).date_naive();
+
+    let mut date_vectors = vec![];
+    for date in [today, today + Duration::days(1)] {
+        let date = date.format("%Y-%m-%d").to_string();
+        date_vectors.append(&mut fetch_delivery_date(&uri, &date, area)?);
+    }
+
+    Ok(date_vectors)
+}
+
+/// The Nord Pool Data Portal behind `DATA_PORTAL_URI`.
+pub struct DataPortalSource;
+
+impl PriceSource for DataPortalSource {
+    fn name(&self) -> &'static str {
+        "DataPortal"
+    }
+
+    fn fetch(&self, area: &str) -> eyre::Result<PriceMatrix> {
+        fetch_json_from_data_portal(area)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Datelike, TimeZone};
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn sample_matrix(filename: &str, area: &str) -> PriceMatrix {
+        let body = std::fs::read_to_string(filename).unwrap();
+        decode_json(&body, area).unwrap()
+    }
+
+    #[test]
+    fn decodes_full_day() {
+        let matrix = sample_matrix("samples/dataportal_request.json", "EE");
+        assert!(matrix.len() == 1);
+        let column = matrix[0].as_ref().unwrap();
+        assert!(column.date.day() == 10);
+        assert!(column.cells.0.len() == 24);
+
+        let first = &column.cells.0[0];
+        let midnight = MARKET_TZ.with_ymd_and_hms(2024, 10, 10, 0, 0, 0).unwrap();
+        assert!(first.moment == midnight);
+        assert!(first.market_hour == 0);
+        assert!(first.price == PricePerMwh(dec!(71.81)));
+
+        let last = &column.cells.0[23];
+        assert!(last.market_hour == 23);
+        assert!(last.interval == Duration::hours(1));
+    }
+
+    #[test]
+    fn decodes_quarter_hours() {
+        let matrix = sample_matrix("samples/dataportal_request_15min.json", "EE");
+        let column = matrix[0].as_ref().unwrap();
+        assert!(column.cells.0.len() == 96);
+        let cell = &column.cells.0[5];
+        assert!(cell.interval == Duration::minutes(15));
+        assert!(cell.market_hour == 1);
+        let quarter_past_one = MARKET_TZ.with_ymd_and_hms(2025, 10, 10, 1, 15, 0).unwrap();
+        assert!(cell.moment == quarter_past_one);
+    }
+
+    #[test]
+    fn picks_requested_area() {
+        let matrix = sample_matrix("samples/dataportal_request.json", "FI");
+        let column = matrix[0].as_ref().unwrap();
+        assert!(column.cells.0[0].price == PricePerMwh(dec!(23.73)));
+        assert!(column.cells.0.iter().all(|c| c.area == "FI"));
+    }
+
+    #[test]
+    fn decodes_dst_day() {
+        let matrix = sample_matrix("samples/dataportal_request_dst.json", "EE");
+        assert!(matrix.len() == 1);
+        let column = matrix[0].as_ref().unwrap();
+        assert!(column.cells.0.len() == 25);
+        let repeated_hours = column
+            .cells
+            .0
+            .iter()
+            .filter(|cell| cell.market_hour == 2)
+            .count();
+        assert!(repeated_hours == 2);
+    }
+
+    #[test]
+    fn fails_on_missing_area() {
+        let body = std::fs::read_to_string("samples/dataportal_request.json").unwrap();
+        assert!(decode_json(&body, "SE3").is_err());
+    }
+
+    #[test]
+    fn fails_on_legacy_format() {
+        let body = std::fs::read_to_string("samples/request.json").unwrap();
+        assert!(decode_json(&body, "EE").is_err());
+    }
+}
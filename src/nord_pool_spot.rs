@@ -60,7 +60,7 @@ async fn row_iteration(
                     Ok(dec_price) => column.cells.0.push(PriceCell {
                         price: PricePerMwh(dec_price),
                         moment: moment,
-                        tariff_price: Some(PriceCell::get_tariff_price_current(moment)),
+                        tariff_price: None,
                         market_hour: hour,
                     }),
                     Err(_) => continue,
@@ -3,18 +3,24 @@ use json::JsonValue;
 use std::{collections::BTreeMap, env};
 
 use crate::{
-    constants::MARKET_TZ,
+    constants::{HOURLY_INTERVAL, MARKET_TZ},
     nord_pool_meta::{
         convert_hour_to_u32, convert_price_to_decimal, parse_date, retrieve_datetime,
     },
     price_cell::PriceCell,
     price_matrix::{DateColumn, PriceMatrix, PricePerMwh},
+    price_source::PriceSource,
 };
 
-pub fn decode_json(body: &str) -> eyre::Result<PriceMatrix> {
+/// Decodes a marketdata page response, whose columns are
+/// the dates of a single delivery area.
+///
+/// Hours without a price, such as the skipped hour of a DST day,
+/// are left out. Incomplete days are caught by validation.
+pub fn decode_json(body: &str, area: &str) -> eyre::Result<PriceMatrix> {
     let json = json::parse(body)?;
 
-    let mut date_map: BTreeMap<String, Option<DateColumn>> = BTreeMap::new();
+    let mut date_map: BTreeMap<String, DateColumn> = BTreeMap::new();
 
     let rows = &json["data"]["Rows"];
 
@@ -34,36 +40,45 @@ pub fn decode_json(body: &str) -> eyre::Result<PriceMatrix> {
 
                     if !date_map.contains_key(dateline) {
                         let formal_date = parse_date(dateline, &MARKET_TZ)?;
-                        date_map.insert(dateline.to_owned(), Some(DateColumn::new(formal_date)));
+                        date_map.insert(dateline.to_owned(), DateColumn::new(formal_date));
                     }
 
                     let price = cell["Value"].as_str().ok_or(eyre!("Missing Value"))?;
                     let moment = match retrieve_datetime(dateline, hour, &MARKET_TZ) {
                         Ok(v) => v,
-                        Err(_) => continue,
+                        Err(e) => {
+                            eprintln!("Skipping {} hour {}: {}", dateline, hour, e);
+                            continue;
+                        }
+                    };
+                    let dec_price = match convert_price_to_decimal(price) {
+                        Ok(v) => v,
+                        Err(_) => {
+                            eprintln!("Skipping {} hour {}: no price {:?}", dateline, hour, price);
+                            continue;
+                        }
                     };
 
-                    match convert_price_to_decimal(price) {
-                        Ok(dec_price) => match date_map.get_mut(dateline).unwrap() {
-                            Some(cells) => cells.cells.0.push(PriceCell {
-                                price: PricePerMwh(dec_price),
-                                moment,
-                                tariff_price: Some(PriceCell::get_tariff_price_current(moment)),
-                                market_hour: hour,
-                            }),
-                            None => todo!(),
-                        },
-                        Err(_) => continue,
-                    }
+                    date_map.get_mut(dateline).unwrap().cells.0.push(PriceCell {
+                        price: PricePerMwh(dec_price),
+                        moment,
+                        tariff_price: None,
+                        market_hour: hour,
+                        interval: HOURLY_INTERVAL,
+                        source: None,
+                        area: area.to_owned(),
+                        estimated: false,
+                        breakdown: None,
+                    });
                 }
             }
         }
     }
-    let map_to_vec = date_map.into_values().collect();
+    let map_to_vec = date_map.into_values().map(Some).collect();
     Ok(map_to_vec)
 }
 
-pub fn fetch_json_from_nord_pool() -> eyre::Result<PriceMatrix> {
+pub fn fetch_json_from_nord_pool(area: &str) -> eyre::Result<PriceMatrix> {
     // let body = reqwest::get(env::var("JSON_URI")?).await?.text().await?;
     let uri = env::var("JSON_URI")?;
     let body = ureq::get(&uri).call()?.into_string()?;
@@ -71,9 +86,22 @@ pub fn fetch_json_from_nord_pool() -> eyre::Result<PriceMatrix> {
     // requestjson.write_all(&body.as_bytes())?;
 
     // let body = std::fs::read_to_string("samples/request.json")?;
-    let date_vectors = decode_json(&body)?;
+    let date_vectors = decode_json(&body, area)?;
 
     println!("{:?}", date_vectors);
 
     Ok(date_vectors)
 }
+
+/// The legacy Nord Pool marketdata page behind `JSON_URI`.
+pub struct NordPoolSource;
+
+impl PriceSource for NordPoolSource {
+    fn name(&self) -> &'static str {
+        "NordPool"
+    }
+
+    fn fetch(&self, area: &str) -> eyre::Result<PriceMatrix> {
+        fetch_json_from_nord_pool(area)
+    }
+}
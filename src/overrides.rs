@@ -60,7 +60,11 @@ pub fn apply_overrides(
             .iter()
             .find(|&oride| oride.day == day && oride.hour == hour);
         if let Some(oride) = oride {
-            pcu.state = oride.state;
+            let reason = match oride.state {
+                PowerState::On => format!("Override: always on at hour {}", hour),
+                PowerState::Off => format!("Override: always off at hour {}", hour),
+            };
+            pcu.set_state(oride.state, reason);
         }
     }
 }
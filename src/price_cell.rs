@@ -1,15 +1,12 @@
-use std::ops::Range;
-
 use crate::{
-    constants::{
-        DAY_TARIFF_PRICE_DECEMBER_2022, DAY_TARIFF_PRICE_JANUARY_2023,
-        DAY_TARIFF_PRICE_OCTOBER_2022, NIGHT_TARIFF_PRICE_DECEMBER_2022,
-        NIGHT_TARIFF_PRICE_JANUARY_2023, NIGHT_TARIFF_PRICE_OCTOBER_2022,
-    },
+    price_components::{PriceBasis, PriceBreakdown, PriceComponent},
     price_matrix::DaySlice,
-    schema::price_cells, tariff,
+    schema::{price_cells, price_revisions},
+    strategy::power_state_model::PowerStateDB,
+    tariff::TariffPackage,
+    tariff_price::TariffTable,
 };
-use chrono::{Date, DateTime, TimeZone, Utc};
+use chrono::{DateTime, Duration, Utc};
 use chrono_tz::Tz;
 use diesel::{prelude::*, PgConnection};
 use eyre::Result;
@@ -17,25 +14,38 @@ use now::DateTimeNow;
 use rust_decimal::Decimal;
 
 use crate::{
-    constants::{DAY_TARIFF_PRICE, MARKET_TZ, NIGHT_TARIFF_PRICE},
-    price_matrix::{CentsPerKwh, PricePerMwh},
-    tariff::Tariff,
+    constants::MARKET_TZ,
+    price_matrix::PricePerMwh,
 };
 
 #[derive(Clone, Debug, Queryable)]
 pub struct PriceCell {
     pub price: PricePerMwh,
     pub moment: DateTime<Tz>,
+    /// Network tariff of the configured package, attached when
+    /// planning and never stored.
     pub tariff_price: Option<PricePerMwh>,
     pub market_hour: u32,
+    /// Length of the market time unit this price is valid for,
+    /// starting from `moment`.
+    pub interval: Duration,
+    /// Name of the price source this cell was fetched from.
+    pub source: Option<String>,
+    /// Bidding zone the price applies to, e.g. `EE` or `SE3`.
+    pub area: String,
+    /// Whether the price is an estimate standing in
+    /// for one not yet published.
+    pub estimated: bool,
+    /// Consumer price of the cell split into its parts, once
+    /// price components have been attached.
+    pub breakdown: Option<PriceBreakdown>,
 }
 
-pub fn get_hour_start_end(datetime: &DateTime<Tz>) -> Result<Range<DateTime<Tz>>> {
-    // let start = datetime.date().and_hms(datetime.hour(), 0, 0);
-    // let end = datetime.date().and_hms(datetime.hour() + 1, 0, 0);
-    let start = datetime.beginning_of_hour();
-    let end = datetime.end_of_hour();
-    Ok(Range { start, end })
+#[derive(Debug, PartialEq, Eq)]
+pub enum CellChange {
+    Unchanged,
+    Inserted,
+    Revised,
 }
 
 pub fn get_day_start_end(moment: &DateTime<Tz>) -> Result<(DateTime<Tz>, DateTime<Tz>)> {
@@ -44,91 +54,31 @@ pub fn get_day_start_end(moment: &DateTime<Tz>) -> Result<(DateTime<Tz>, DateTim
     Ok((midnight_start, midnight_end))
 }
 
-const UNABLE_ERR: &str = "Unable to construct DateTime that surely must exist!";
-
 impl PriceCell {
-    pub fn get_tariff_price(
-        moment: DateTime<Tz>,
-        day_tariff: &CentsPerKwh,
-        night_tariff: &CentsPerKwh,
-    ) -> PricePerMwh {
-        let tariff = Tariff::get_tariff(&moment);
-        let tariff_value = match tariff {
-            Tariff::Night => night_tariff,
-            Tariff::Day => day_tariff,
-        };
-        PricePerMwh::from(tariff_value)
+    /// Sets the network tariff price of the package valid at the cell's moment.
+    pub fn attach_tariff(&mut self, package: &TariffPackage, tariffs: &TariffTable) {
+        self.tariff_price = tariffs.price_at(package, &self.moment);
     }
 
-    fn day_tariff_price<'a>(moment: &DateTime<Tz>) -> &'a CentsPerKwh {
-        if moment
-            < &MARKET_TZ
-                .with_ymd_and_hms(2022, 6, 1, 0, 0, 0)
-                .earliest()
-                .expect(UNABLE_ERR)
-        {
-            &DAY_TARIFF_PRICE
-        } else if moment
-            < &MARKET_TZ
-                .with_ymd_and_hms(2022, 12, 1, 0, 0, 0)
-                .earliest()
-                .expect(UNABLE_ERR)
-        {
-            &DAY_TARIFF_PRICE_OCTOBER_2022
-        } else if moment
-            < &MARKET_TZ
-                .with_ymd_and_hms(2023, 1, 1, 0, 0, 0)
-                .earliest()
-                .expect(UNABLE_ERR)
-        {
-            &DAY_TARIFF_PRICE_DECEMBER_2022
-        } else {
-            &DAY_TARIFF_PRICE_JANUARY_2023
-        }
+    /// Splits the consumer price into its parts using the configured
+    /// components. The tariff has to be attached first.
+    pub fn attach_components(&mut self, components: &[PriceComponent]) {
+        self.breakdown = Some(PriceBreakdown::new(self, components));
     }
 
-    fn night_tariff_price<'a>(moment: &DateTime<Tz>) -> &'a CentsPerKwh {
-        if moment
-            < &MARKET_TZ
-                .with_ymd_and_hms(2022, 6, 1, 0, 0, 0)
-                .earliest()
-                .expect(UNABLE_ERR)
-        {
-            &NIGHT_TARIFF_PRICE
-        } else if moment
-            < &MARKET_TZ
-                .with_ymd_and_hms(2022, 12, 1, 0, 0, 0)
-                .earliest()
-                .expect(UNABLE_ERR)
-        {
-            &NIGHT_TARIFF_PRICE_OCTOBER_2022
-        } else if moment
-            < &MARKET_TZ
-                .with_ymd_and_hms(2022, 1, 1, 0, 0, 0)
-                .earliest()
-                .expect(UNABLE_ERR)
-        {
-            &NIGHT_TARIFF_PRICE_DECEMBER_2022
-        } else {
-            &NIGHT_TARIFF_PRICE_JANUARY_2023
+    /// The price strategies compare on the given basis. Without a
+    /// breakdown the consumer price falls back to spot and tariff.
+    pub fn price_on(&self, basis: PriceBasis) -> PricePerMwh {
+        match (basis, &self.breakdown) {
+            (PriceBasis::Spot, _) => self.price.clone(),
+            (PriceBasis::Consumer, Some(breakdown)) => breakdown.consumer(),
+            _ => self.total(),
         }
     }
 
-    pub fn get_tariff_price_current(moment: DateTime<Tz>) -> PricePerMwh {
-        Self::get_tariff_price(
-            moment,
-            PriceCell::day_tariff_price(&moment),
-            PriceCell::night_tariff_price(&moment),
-        )
-    }
-
-    fn add_tariff(&mut self, day_tariff: &CentsPerKwh, night_tariff: &CentsPerKwh) {
-        let tariff = Tariff::get_tariff(&self.moment);
-        let tariff_value = match tariff {
-            Tariff::Night => night_tariff,
-            Tariff::Day => day_tariff,
-        };
-        self.tariff_price = Some(tariff_value.into());
+    /// The moment this price stops being valid.
+    pub fn end(&self) -> DateTime<Tz> {
+        self.moment + self.interval
     }
 
     pub fn total(&self) -> PricePerMwh {
@@ -139,54 +89,142 @@ impl PriceCell {
         PricePerMwh(price)
     }
 
-    /// Fetches all prices on the given date in its given timezone.
-    pub fn get_prices_from_db(connection: &mut PgConnection, date: &DateTime<Tz>) -> Result<DaySlice> {
+    /// Fetches all prices of an area on the given date in its given timezone.
+    pub fn get_prices_from_db(
+        connection: &mut PgConnection,
+        date: &DateTime<Tz>,
+        area_code: &str,
+    ) -> Result<DaySlice> {
+        let (midnight_start, midnight_end) = get_day_start_end(date)?;
+        let cells =
+            PriceCell::get_range_from_db(connection, &midnight_start, &midnight_end, area_code)?;
+        Ok(DaySlice(cells))
+    }
+
+    /// Fetches all prices of an area starting within the given range.
+    pub fn get_range_from_db(
+        connection: &mut PgConnection,
+        start: &DateTime<Tz>,
+        end: &DateTime<Tz>,
+        area_code: &str,
+    ) -> Result<Vec<PriceCell>> {
         use self::price_cells::dsl::*;
 
-        let (midnight_start, midnight_end) = get_day_start_end(date)?;
         let cells = price_cells
-            .filter(moment_utc.ge(&midnight_start))
-            .filter(moment_utc.lt(&midnight_end))
+            .filter(area.eq(area_code))
+            .filter(moment_utc.ge(start))
+            .filter(moment_utc.lt(end))
+            .order_by(moment_utc)
             .load::<PriceCellDB>(connection)?;
-        let cells = cells.into_iter().map(|pcdb| pcdb.into()).collect();
-        Ok(DaySlice(cells))
+        Ok(cells.into_iter().map(|pcdb| pcdb.into()).collect())
     }
 
-    pub fn insert_cell_into_database(&self, connection: &mut PgConnection) -> eyre::Result<()> {
+    /// Inserts the cell, or updates the price and length already stored
+    /// for its area and moment. Stored cells of another resolution
+    /// overlapping it are deleted. A changed price is recorded in
+    /// `price_revisions` along with both sources.
+    pub fn insert_cell_into_database(
+        &self,
+        connection: &mut PgConnection,
+    ) -> eyre::Result<CellChange> {
         use self::price_cells::dsl::*;
 
+        if self.estimated {
+            return Err(eyre::eyre!("Refusing to store estimated price at {}", self.moment));
+        }
+
         let utc = self.moment.with_timezone(&Utc);
-        let count = price_cells
-            .filter(moment_utc.eq(&utc))
-            .limit(5)
-            .count()
-            .get_result::<i64>(connection)?;
-
-        if count == 0 {
-            let tariff = self.tariff_price.as_ref().map(|o| &o.0);
-            let new_price = NewPriceCellDB {
-                price_mwh: &self.price.0,
-                moment_utc: self.moment.with_timezone(&Utc),
-                tariff_mwh: tariff,
-                market_hour: self.market_hour.try_into().unwrap(),
+        let end_utc = self.end().with_timezone(&Utc);
+        let minutes: i16 = self.interval.num_minutes().try_into()?;
+
+        connection.transaction::<_, eyre::Error, _>(|connection| {
+            // No market time unit is longer than a day
+            let overlapping: Vec<i32> = price_cells
+                .filter(area.eq(&self.area))
+                .filter(moment_utc.gt(utc - Duration::days(1)))
+                .filter(moment_utc.lt(end_utc))
+                .filter(moment_utc.ne(utc))
+                .load::<PriceCellDB>(connection)?
+                .into_iter()
+                .filter(|cell| {
+                    cell.moment_utc + Duration::minutes(cell.interval_minutes.into()) > utc
+                })
+                .map(|cell| cell.id)
+                .collect();
+            diesel::delete(price_cells.filter(id.eq_any(&overlapping))).execute(connection)?;
+
+            let existing = price_cells
+                .filter(area.eq(&self.area))
+                .filter(moment_utc.eq(&utc))
+                .order_by(id.desc())
+                .first::<PriceCellDB>(connection)
+                .optional()?;
+
+            let existing = match existing {
+                Some(v) => v,
+                None => {
+                    let new_price = NewPriceCellDB::new(self);
+                    diesel::insert_into(price_cells)
+                        .values(&new_price)
+                        .execute(connection)?;
+                    return Ok(CellChange::Inserted);
+                }
             };
 
-            diesel::insert_into(price_cells)
-                .values(&new_price)
-                .get_result::<PriceCellDB>(connection)?;
-        }
+            let price_changed = existing.price != self.price.0;
+            if !price_changed && existing.interval_minutes == minutes && overlapping.is_empty() {
+                return Ok(CellChange::Unchanged);
+            }
 
-        Ok(())
+            if price_changed {
+                diesel::insert_into(price_revisions::table)
+                    .values(NewPriceRevisionDB {
+                        price_cell_id: existing.id,
+                        old_price_mwh: &existing.price,
+                        new_price_mwh: &self.price.0,
+                        old_source: existing.source.as_deref(),
+                        new_source: self.source.as_deref(),
+                    })
+                    .execute(connection)?;
+            }
+            diesel::update(price_cells.find(existing.id))
+                .set((
+                    price_mwh.eq(&self.price.0),
+                    source.eq(&self.source),
+                    interval_minutes.eq(minutes),
+                ))
+                .execute(connection)?;
+            Ok(CellChange::Revised)
+        })
     }
 
+    /// Inserts or revises every cell. Cached power states of the day
+    /// of `now` and the next covered by a new or revised price are
+    /// marked stale, as they were planned without it.
+    ///
+    /// Returns the number of revised prices and resolutions.
     pub fn insert_cells_into_database(
         connection: &mut PgConnection,
         prices: &Vec<PriceCell>,
-    ) -> eyre::Result<()> {
+        now: &DateTime<Tz>,
+    ) -> eyre::Result<usize> {
+        let (replan_start, _) = get_day_start_end(now)?;
+        let replan_end = replan_start + Duration::days(2);
+
+        let mut revised = 0;
         for price in prices {
-            price.insert_cell_into_database(connection)?;
+            match price.insert_cell_into_database(connection)? {
+                CellChange::Unchanged => continue,
+                CellChange::Inserted => (),
+                CellChange::Revised => revised += 1,
+            }
+            let start = price.moment.with_timezone(&Utc);
+            let end = price.end().with_timezone(&Utc);
+            if end > replan_start && start < replan_end {
+                PowerStateDB::mark_stale(connection, &start, &end)?;
+            }
         }
-        Ok(())
+        Ok(revised)
     }
 }
 
@@ -199,19 +237,25 @@ pub struct PriceCellDB {
     id: i32,
     price: Decimal,
     moment_utc: DateTime<Utc>,
-    tariff: Option<Decimal>,
     market_hour: i16,
     created_at: DateTime<Utc>,
+    interval_minutes: i16,
+    source: Option<String>,
+    area: String,
 }
 
 impl From<PriceCellDB> for PriceCell {
     fn from(pcdb: PriceCellDB) -> Self {
-        let tariff_price = pcdb.tariff.map(PricePerMwh);
         PriceCell {
             price: PricePerMwh(pcdb.price),
             moment: pcdb.moment_utc.with_timezone(&MARKET_TZ),
-            tariff_price,
+            tariff_price: None,
             market_hour: pcdb.market_hour.try_into().unwrap(),
+            interval: Duration::minutes(pcdb.interval_minutes.into()),
+            source: pcdb.source,
+            area: pcdb.area,
+            estimated: false,
+            breakdown: None,
         }
     }
 }
@@ -221,18 +265,144 @@ impl From<PriceCellDB> for PriceCell {
 pub struct NewPriceCellDB<'a> {
     pub price_mwh: &'a Decimal,
     pub moment_utc: DateTime<Utc>,
-    pub tariff_mwh: Option<&'a Decimal>,
     pub market_hour: i16,
+    pub interval_minutes: i16,
+    pub source: Option<&'a str>,
+    pub area: &'a str,
 }
 
 impl<'a> NewPriceCellDB<'a> {
     fn new(pc: &'a PriceCell) -> Self {
-        let tariff_mwh = pc.tariff_price.as_ref().map(|ppm| &ppm.0);
         NewPriceCellDB {
             price_mwh: &pc.price.0,
             moment_utc: pc.moment.with_timezone(&Utc),
-            tariff_mwh,
             market_hour: pc.market_hour.try_into().unwrap(),
+            interval_minutes: pc.interval.num_minutes().try_into().unwrap(),
+            source: pc.source.as_deref(),
+            area: &pc.area,
         }
     }
 }
+
+#[derive(Insertable)]
+#[table_name = "price_revisions"]
+pub struct NewPriceRevisionDB<'a> {
+    pub price_cell_id: i32,
+    pub old_price_mwh: &'a Decimal,
+    pub new_price_mwh: &'a Decimal,
+    pub old_source: Option<&'a str>,
+    pub new_source: Option<&'a str>,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use diesel::Connection;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::{
+        constants::PLANNING_TZ,
+        database,
+        price_matrix::expand_to_interval,
+        sample_data::tests::sample_day,
+        strategy::{PowerState, PriceChangeUnit},
+    };
+
+    #[test]
+    #[serial]
+    fn keeps_areas_apart() {
+        let mut connection = database::establish_connection();
+        connection.test_transaction::<_, eyre::Error, _>(|connection| {
+            let start = MARKET_TZ.with_ymd_and_hms(2031, 10, 10, 0, 0, 0).unwrap();
+            let estonia = sample_day(&start, 0, 24, &mut rand::rng())?.0;
+            let finland: Vec<PriceCell> = estonia
+                .iter()
+                .map(|cell| PriceCell {
+                    area: "FI".to_owned(),
+                    ..cell.clone()
+                })
+                .collect();
+            PriceCell::insert_cells_into_database(connection, &estonia, &start)?;
+            PriceCell::insert_cells_into_database(connection, &finland, &start)?;
+            PriceCell::insert_cells_into_database(connection, &finland, &start)?;
+
+            let fetched = PriceCell::get_prices_from_db(connection, &start, "FI")?;
+            assert!(fetched.0.len() == 24);
+            assert!(fetched.0.iter().all(|cell| cell.area == "FI"));
+            let fetched = PriceCell::get_prices_from_db(connection, &start, "LV")?;
+            assert!(fetched.0.is_empty());
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[serial]
+    fn revises_changed_prices() {
+        let mut connection = database::establish_connection();
+        connection.test_transaction::<_, eyre::Error, _>(|connection| {
+            let start = PLANNING_TZ.with_ymd_and_hms(2031, 10, 13, 0, 0, 0).unwrap();
+            let cells = sample_day(&start, 0, 24, &mut rand::rng())?.0;
+            let states: Vec<PriceChangeUnit> = cells
+                .iter()
+                .map(|cell| PriceChangeUnit {
+                    moment: cell.moment,
+                    interval: cell.interval,
+                    state: PowerState::On,
+                    price: None,
+                    reasons: vec![],
+                    held_off: false,
+                })
+                .collect();
+            assert!(PriceCell::insert_cells_into_database(connection, &cells, &start)? == 0);
+            PowerStateDB::insert_day_into_database(connection, &states, None);
+
+            let mut revised = cells.clone();
+            revised[3].price = PricePerMwh(revised[3].price.0 + Decimal::ONE);
+            revised[3].source = Some("Entsoe".to_owned());
+            assert!(PriceCell::insert_cells_into_database(connection, &revised, &start)? == 1);
+
+            let fetched = PriceCell::get_prices_from_db(connection, &start, &cells[3].area)?;
+            assert!(fetched.0.len() == 24);
+            assert!(fetched.0[3].price == revised[3].price);
+            assert!(fetched.0[3].source == Some("Entsoe".to_owned()));
+
+            let old_prices = price_revisions::table
+                .select(price_revisions::old_price_mwh)
+                .filter(price_revisions::new_price_mwh.eq(&revised[3].price.0))
+                .load::<Decimal>(connection)?;
+            assert!(old_prices == vec![cells[3].price.0]);
+
+            let cached = PowerStateDB::get_day_from_database(connection, &start, None)?;
+            assert!(cached.len() == 23);
+            assert!(cached.iter().all(|pcu| pcu.moment != cells[3].moment));
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[serial]
+    fn replaces_cells_of_another_resolution() {
+        let mut connection = database::establish_connection();
+        connection.test_transaction::<_, eyre::Error, _>(|connection| {
+            let start = MARKET_TZ.with_ymd_and_hms(2031, 10, 11, 0, 0, 0).unwrap();
+            let hourly = sample_day(&start, 0, 24, &mut rand::rng())?;
+            let quarters = expand_to_interval(&hourly, Duration::minutes(15));
+            let area_code = &hourly.0[0].area;
+            PriceCell::insert_cells_into_database(connection, &hourly.0, &start)?;
+            // Same prices, only the resolution differs
+            assert!(PriceCell::insert_cells_into_database(connection, &quarters.0, &start)? == 24);
+
+            let fetched = PriceCell::get_prices_from_db(connection, &start, area_code)?;
+            assert!(fetched.0.len() == 96);
+            assert!(fetched.0.iter().all(|cell| cell.interval == Duration::minutes(15)));
+            assert!(fetched.0.windows(2).all(|pair| pair[0].end() == pair[1].moment));
+
+            PriceCell::insert_cells_into_database(connection, &hourly.0, &start)?;
+            let fetched = PriceCell::get_prices_from_db(connection, &start, area_code)?;
+            assert!(fetched.0.len() == 24);
+            assert!(fetched.0.iter().all(|cell| cell.interval == Duration::hours(1)));
+            Ok(())
+        });
+    }
+}
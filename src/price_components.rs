@@ -0,0 +1,200 @@
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::{constants::LOCAL_TZ, price_cell::PriceCell, price_matrix::PricePerMwh};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub enum ComponentKind {
+    /// Seller margin in €/MWh.
+    Margin,
+    /// Renewable energy fee in €/MWh.
+    RenewableFee,
+    /// Electricity excise in €/MWh.
+    Excise,
+    /// Value added tax in percent of everything else.
+    Vat,
+}
+
+/// A part of the consumer price on top of the spot price and
+/// network tariff, valid from `valid_from` until the day before
+/// `valid_until`, local dates. Either end may be left open.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PriceComponent {
+    pub kind: ComponentKind,
+    pub value: Decimal,
+    pub valid_from: Option<NaiveDate>,
+    pub valid_until: Option<NaiveDate>,
+}
+
+impl PriceComponent {
+    pub fn applies_at(&self, moment: &DateTime<Tz>) -> bool {
+        let date = moment.with_timezone(&LOCAL_TZ).date_naive();
+        self.valid_from.is_none_or(|from| from <= date)
+            && self.valid_until.is_none_or(|until| date < until)
+    }
+}
+
+/// Value of a component kind at a moment. Of several valid
+/// components of a kind the last one listed applies.
+fn value_at(components: &[PriceComponent], kind: ComponentKind, moment: &DateTime<Tz>) -> Decimal {
+    components
+        .iter()
+        .rev()
+        .find(|c| c.kind == kind && c.applies_at(moment))
+        .map(|c| c.value)
+        .unwrap_or(Decimal::ZERO)
+}
+
+/// Which price strategies compare against their limits. Strategies
+/// left without one use `Network`, the spot price and tariff.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub enum PriceBasis {
+    /// The market price alone.
+    Spot,
+    /// Market price and network tariff.
+    #[default]
+    Network,
+    /// Everything on the bill, VAT included.
+    Consumer,
+}
+
+/// Consumer price of a cell split into its parts, all in €/MWh.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PriceBreakdown {
+    pub spot: PricePerMwh,
+    pub tariff: PricePerMwh,
+    pub margin: PricePerMwh,
+    pub renewable_fee: PricePerMwh,
+    pub excise: PricePerMwh,
+    pub vat: PricePerMwh,
+}
+
+impl PriceBreakdown {
+    pub fn new(cell: &PriceCell, components: &[PriceComponent]) -> PriceBreakdown {
+        let moment = &cell.moment;
+        let spot = cell.price.0;
+        let tariff = cell.tariff_price.as_ref().map(|t| t.0).unwrap_or(Decimal::ZERO);
+        let margin = value_at(components, ComponentKind::Margin, moment);
+        let renewable_fee = value_at(components, ComponentKind::RenewableFee, moment);
+        let excise = value_at(components, ComponentKind::Excise, moment);
+        let before_vat = spot + tariff + margin + renewable_fee + excise;
+        let vat_percent = value_at(components, ComponentKind::Vat, moment);
+        let vat = before_vat * vat_percent / Decimal::ONE_HUNDRED;
+        PriceBreakdown {
+            spot: PricePerMwh(spot),
+            tariff: PricePerMwh(tariff),
+            margin: PricePerMwh(margin),
+            renewable_fee: PricePerMwh(renewable_fee),
+            excise: PricePerMwh(excise),
+            vat: PricePerMwh(vat),
+        }
+    }
+
+    pub fn consumer(&self) -> PricePerMwh {
+        PricePerMwh(
+            self.spot.0
+                + self.tariff.0
+                + self.margin.0
+                + self.renewable_fee.0
+                + self.excise.0
+                + self.vat.0,
+        )
+    }
+}
+
+impl fmt::Display for PriceBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "spot {} + tariff {} + margin {} + renewable {} + excise {} + VAT {} = {} €/MWh",
+            self.spot.0.round_dp(2),
+            self.tariff.0.round_dp(2),
+            self.margin.0.round_dp(2),
+            self.renewable_fee.0.round_dp(2),
+            self.excise.0.round_dp(2),
+            self.vat.0.round_dp(2),
+            self.consumer().0.round_dp(2)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Timelike};
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::constants::{DEFAULT_AREA, HOURLY_INTERVAL, MARKET_TZ};
+
+    fn cell(moment: DateTime<Tz>) -> PriceCell {
+        PriceCell {
+            price: PricePerMwh(dec!(100)),
+            moment,
+            tariff_price: Some(PricePerMwh(dec!(50))),
+            market_hour: moment.hour(),
+            interval: HOURLY_INTERVAL,
+            source: None,
+            area: DEFAULT_AREA.to_owned(),
+            estimated: false,
+            breakdown: None,
+        }
+    }
+
+    fn component(
+        kind: ComponentKind,
+        value: Decimal,
+        from: Option<(i32, u32, u32)>,
+    ) -> PriceComponent {
+        PriceComponent {
+            kind,
+            value,
+            valid_from: from.and_then(|(y, m, d)| NaiveDate::from_ymd_opt(y, m, d)),
+            valid_until: None,
+        }
+    }
+
+    #[test]
+    fn adds_vat_on_top() {
+        let components = vec![
+            component(ComponentKind::Margin, dec!(5), None),
+            component(ComponentKind::RenewableFee, dec!(10), None),
+            component(ComponentKind::Excise, dec!(15), None),
+            component(ComponentKind::Vat, dec!(20), None),
+        ];
+        let moment = MARKET_TZ.with_ymd_and_hms(2024, 3, 12, 12, 0, 0).unwrap();
+        let breakdown = PriceBreakdown::new(&cell(moment), &components);
+        assert!(breakdown.vat == PricePerMwh(dec!(36)));
+        assert!(breakdown.consumer() == PricePerMwh(dec!(216)));
+    }
+
+    #[test]
+    fn follows_validity_dates() {
+        let components = vec![
+            component(ComponentKind::Vat, dec!(22), None),
+            component(ComponentKind::Vat, dec!(24), Some((2025, 7, 1))),
+        ];
+        let june = MARKET_TZ.with_ymd_and_hms(2025, 6, 30, 12, 0, 0).unwrap();
+        let breakdown = PriceBreakdown::new(&cell(june), &components);
+        assert!(breakdown.vat == PricePerMwh(dec!(33)));
+        // Half past eleven in market time is already July locally
+        let july = MARKET_TZ.with_ymd_and_hms(2025, 6, 30, 23, 30, 0).unwrap();
+        let breakdown = PriceBreakdown::new(&cell(july), &components);
+        assert!(breakdown.vat == PricePerMwh(dec!(36)));
+    }
+
+    #[test]
+    fn compares_on_basis() {
+        let components = vec![component(ComponentKind::Vat, dec!(20), None)];
+        let moment = MARKET_TZ.with_ymd_and_hms(2024, 3, 12, 12, 0, 0).unwrap();
+        let mut cell = cell(moment);
+        assert!(cell.price_on(PriceBasis::Consumer) == PricePerMwh(dec!(150)));
+        cell.attach_components(&components);
+        assert!(cell.price_on(PriceBasis::Spot) == PricePerMwh(dec!(100)));
+        assert!(cell.price_on(PriceBasis::Network) == PricePerMwh(dec!(150)));
+        assert!(cell.price_on(PriceBasis::Consumer) == PricePerMwh(dec!(180)));
+    }
+}
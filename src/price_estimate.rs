@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Datelike, Duration, Timelike};
+use chrono_tz::Tz;
+use diesel::PgConnection;
+use rust_decimal::Decimal;
+
+use crate::{
+    constants::{HOURLY_INTERVAL, MARKET_TZ},
+    price_cell::PriceCell,
+    price_matrix::{DaySlice, PricePerMwh},
+};
+
+const ESTIMATE_SOURCE: &str = "Estimate";
+
+/// Weeks of history an estimate is drawn from.
+const HISTORY_WEEKS: i64 = 4;
+
+/// Days at the end of the history taken as the current price level.
+const TREND_DAYS: i64 = 7;
+
+fn mean(prices: &[Decimal]) -> Option<Decimal> {
+    if prices.is_empty() {
+        return None;
+    }
+    Some(prices.iter().sum::<Decimal>() / Decimal::from(prices.len()))
+}
+
+/// Estimates hourly prices from `day_start` to `day_end` from past
+/// prices: the average of the same weekday and hour, shifted by how
+/// much the last week differs from the whole history. Hours with no
+/// history at all are left out.
+pub fn estimate_from_history(
+    history: &[PriceCell],
+    day_start: &DateTime<Tz>,
+    day_end: &DateTime<Tz>,
+) -> Vec<PriceCell> {
+    let Some(area) = history.first().map(|cell| cell.area.clone()) else {
+        return vec![];
+    };
+
+    let mut by_weekday_hour: BTreeMap<(u32, u32), Vec<Decimal>> = BTreeMap::new();
+    let mut by_hour: BTreeMap<u32, Vec<Decimal>> = BTreeMap::new();
+    for cell in history {
+        let moment = cell.moment.with_timezone(&MARKET_TZ);
+        let key = (moment.weekday().num_days_from_monday(), moment.hour());
+        by_weekday_hour.entry(key).or_default().push(cell.price.0);
+        by_hour.entry(moment.hour()).or_default().push(cell.price.0);
+    }
+
+    let all: Vec<Decimal> = history.iter().map(|cell| cell.price.0).collect();
+    let trend_start = *day_start - Duration::days(TREND_DAYS);
+    let recent: Vec<Decimal> = history
+        .iter()
+        .filter(|cell| cell.moment >= trend_start)
+        .map(|cell| cell.price.0)
+        .collect();
+    let trend = match (mean(&recent), mean(&all)) {
+        (Some(recent), Some(all)) => recent - all,
+        _ => Decimal::ZERO,
+    };
+
+    let mut cells = vec![];
+    let mut moment = *day_start;
+    while moment < *day_end {
+        let local = moment.with_timezone(&MARKET_TZ);
+        let profile = by_weekday_hour
+            .get(&(local.weekday().num_days_from_monday(), local.hour()))
+            .or_else(|| by_hour.get(&local.hour()))
+            .and_then(|prices| mean(prices));
+        if let Some(profile) = profile {
+            cells.push(PriceCell {
+                price: PricePerMwh((profile + trend).round_dp(2)),
+                moment,
+                tariff_price: None,
+                market_hour: local.hour(),
+                interval: HOURLY_INTERVAL,
+                source: Some(ESTIMATE_SOURCE.to_owned()),
+                area: area.clone(),
+                estimated: true,
+                breakdown: None,
+            });
+        }
+        moment += HOURLY_INTERVAL;
+    }
+    cells
+}
+
+/// Adds the estimates not overlapping any known price to the day.
+pub fn fill_missing(known: &DaySlice, estimates: Vec<PriceCell>) -> DaySlice {
+    let mut cells = known.0.clone();
+    for estimate in estimates {
+        let overlaps = known
+            .0
+            .iter()
+            .any(|cell| cell.moment < estimate.end() && estimate.moment < cell.end());
+        if !overlaps {
+            cells.push(estimate);
+        }
+    }
+    cells.sort_by_key(|cell| cell.moment);
+    DaySlice(cells)
+}
+
+/// Fills the intervals of a day that have no published price
+/// with estimates drawn from the prices stored before it.
+pub fn estimate_missing(
+    connection: &mut PgConnection,
+    known: &DaySlice,
+    day_start: &DateTime<Tz>,
+    day_end: &DateTime<Tz>,
+    area: &str,
+) -> eyre::Result<DaySlice> {
+    let history_start = *day_start - Duration::weeks(HISTORY_WEEKS);
+    let history = PriceCell::get_range_from_db(connection, &history_start, day_start, area)?;
+    let estimates = estimate_from_history(&history, day_start, day_end);
+    Ok(fill_missing(known, estimates))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Weekday};
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::constants::DEFAULT_AREA;
+
+    fn cell(moment: DateTime<Tz>, price: Decimal) -> PriceCell {
+        PriceCell {
+            price: PricePerMwh(price),
+            moment,
+            tariff_price: None,
+            market_hour: moment.hour(),
+            interval: HOURLY_INTERVAL,
+            source: None,
+            area: DEFAULT_AREA.to_owned(),
+            estimated: false,
+            breakdown: None,
+        }
+    }
+
+    /// Two weeks of prices where every hour costs its hour number,
+    /// Sundays cost 100 more, and the second week 10 more.
+    fn history(day_start: &DateTime<Tz>) -> Vec<PriceCell> {
+        let mut cells = vec![];
+        let mut moment = *day_start - Duration::weeks(2);
+        while moment < *day_start {
+            let mut price = Decimal::from(moment.hour());
+            if moment.weekday() == Weekday::Sun {
+                price += dec!(100);
+            }
+            if moment >= *day_start - Duration::weeks(1) {
+                price += dec!(10);
+            }
+            cells.push(cell(moment, price));
+            moment += HOURLY_INTERVAL;
+        }
+        cells
+    }
+
+    #[test]
+    fn follows_weekday_profile_and_trend() {
+        // A Sunday
+        let day_start = MARKET_TZ.with_ymd_and_hms(2024, 10, 13, 0, 0, 0).unwrap();
+        let day_end = day_start + Duration::days(1);
+        let estimates = estimate_from_history(&history(&day_start), &day_start, &day_end);
+        assert!(estimates.len() == 24);
+        assert!(estimates.iter().all(|cell| cell.estimated));
+        // Sunday 05:00 averages 105 and 115, shifted by the 5 the
+        // last week is above the average.
+        assert!(estimates[5].price == PricePerMwh(dec!(115)));
+        assert!(estimates[5].moment == day_start + Duration::hours(5));
+    }
+
+    #[test]
+    fn estimates_nothing_without_history() {
+        let day_start = MARKET_TZ.with_ymd_and_hms(2024, 10, 13, 0, 0, 0).unwrap();
+        let day_end = day_start + Duration::days(1);
+        assert!(estimate_from_history(&[], &day_start, &day_end).is_empty());
+    }
+
+    #[test]
+    fn keeps_known_prices() {
+        let day_start = MARKET_TZ.with_ymd_and_hms(2024, 10, 13, 0, 0, 0).unwrap();
+        let day_end = day_start + Duration::days(1);
+        let known = (0..12).map(|h| cell(day_start + Duration::hours(h), dec!(1)));
+        let known = DaySlice(known.collect());
+        let estimates = estimate_from_history(&history(&day_start), &day_start, &day_end);
+        let filled = fill_missing(&known, estimates);
+        assert!(filled.0.len() == 24);
+        assert!(filled.0[..12].iter().all(|cell| !cell.estimated));
+        assert!(filled.0[12..].iter().all(|cell| cell.estimated));
+    }
+}
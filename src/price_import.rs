@@ -0,0 +1,252 @@
+use std::{collections::BTreeMap, path::Path};
+
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, Timelike};
+use chrono_tz::Tz;
+use diesel::PgConnection;
+use eyre::eyre;
+
+use crate::{
+    constants::{HOURLY_INTERVAL, MARKET_TZ},
+    entsoe,
+    nord_pool_data_portal,
+    nord_pool_meta::{convert_price_to_decimal, parse_date},
+    nord_pool_spot_json,
+    price_cell::PriceCell,
+    price_matrix::{self, DateColumn, PriceMatrix, PricePerMwh},
+    price_validation::ValidationConfig,
+};
+
+const IMPORT_SOURCE: &str = "Import";
+
+const NAIVE_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+];
+
+/// Parses a CSV timestamp, either RFC 3339 with an offset or a
+/// plain date and time taken to be in the market timezone. A plain
+/// time of the hour repeated when clocks go back gives the later
+/// moment as well.
+fn parse_timestamp(text: &str) -> eyre::Result<(DateTime<Tz>, Option<DateTime<Tz>>)> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Ok((datetime.with_timezone(&MARKET_TZ), None));
+    }
+    for format in NAIVE_FORMATS {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(text, format) {
+            return match naive.and_local_timezone(MARKET_TZ) {
+                LocalResult::Single(moment) => Ok((moment, None)),
+                LocalResult::Ambiguous(earlier, later) => Ok((earlier, Some(later))),
+                LocalResult::None => Err(eyre!("Nonexistent local time: {}", text)),
+            };
+        }
+    }
+    Err(eyre!("Unable to parse timestamp: {}", text))
+}
+
+/// Shortest step between consecutive cells, sorted by moment.
+fn shortest_step(cells: &[PriceCell]) -> Option<Duration> {
+    cells
+        .windows(2)
+        .map(|pair| pair[1].moment - pair[0].moment)
+        .filter(|step| *step > Duration::zero())
+        .min()
+}
+
+/// Decodes CSV rows of `timestamp,price` in €/MWh. Rows may also be
+/// separated by semicolons, in which case the price may use a decimal
+/// comma. A header row is skipped. The interval of every cell is the
+/// shortest step between consecutive timestamps of its day, so that
+/// a file may span a change of resolution. Plain times of the hour
+/// repeated when clocks go back are told apart by row order.
+pub fn decode_csv(body: &str, area: &str) -> eyre::Result<PriceMatrix> {
+    let mut rows = vec![];
+    for (number, line) in body.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let separator = if line.contains(';') { ';' } else { ',' };
+        let mut fields = line.split(separator).map(str::trim);
+        let (timestamp, price) = match (fields.next(), fields.next()) {
+            (Some(timestamp), Some(price)) => (timestamp, price),
+            _ => return Err(eyre!("Line {} is not timestamp,price: {}", number + 1, line)),
+        };
+        let parsed = parse_timestamp(timestamp).and_then(|(moment, later)| {
+            let price = convert_price_to_decimal(price)?;
+            // A repeated time is the later one once the earlier is read
+            let moment = match (later, rows.last()) {
+                (Some(later), Some((previous, _))) if *previous >= moment => later,
+                _ => moment,
+            };
+            Ok((moment, price))
+        });
+        match parsed {
+            Ok(row) => rows.push(row),
+            Err(_) if number == 0 => continue,
+            Err(e) => return Err(eyre!("Line {}: {}", number + 1, e)),
+        }
+    }
+    rows.sort_by_key(|row| row.0);
+
+    let mut date_map: BTreeMap<String, DateColumn> = BTreeMap::new();
+    for (moment, price) in rows {
+        let dateline = moment.format("%Y-%m-%d").to_string();
+        if !date_map.contains_key(&dateline) {
+            let formal_date = parse_date(&dateline, &MARKET_TZ)?;
+            date_map.insert(dateline.clone(), DateColumn::new(formal_date));
+        }
+        date_map.get_mut(&dateline).unwrap().cells.0.push(PriceCell {
+            price: PricePerMwh(price),
+            moment,
+            tariff_price: None,
+            market_hour: moment.hour(),
+            interval: HOURLY_INTERVAL,
+            source: None,
+            area: area.to_owned(),
+            estimated: false,
+            breakdown: None,
+        });
+    }
+
+    for column in date_map.values_mut() {
+        let interval = shortest_step(&column.cells.0).unwrap_or(HOURLY_INTERVAL);
+        for cell in column.cells.0.iter_mut() {
+            cell.interval = interval;
+        }
+    }
+
+    Ok(date_map.into_values().map(Some).collect())
+}
+
+/// Decodes a price file in any of the supported formats, told apart
+/// by extension and content: CSV, ENTSO-E XML, Data Portal JSON or
+/// a saved legacy Nord Pool JSON response. ENTSO-E documents carry
+/// their own area, other formats are read as prices of `area`.
+pub fn decode_file(filename: &str, area: &str) -> eyre::Result<PriceMatrix> {
+    let body = std::fs::read_to_string(filename)?;
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    match extension.as_deref() {
+        Some("csv") => decode_csv(&body, area),
+        Some("xml") => entsoe::decode_xml(&body),
+        Some("json") if body.contains("multiAreaEntries") => {
+            nord_pool_data_portal::decode_json(&body, area)
+        }
+        Some("json") => nord_pool_spot_json::decode_json(&body, area),
+        _ => Err(eyre!("Unknown price file format: {}", filename)),
+    }
+}
+
+/// Imports every valid day of prices found in the given file into
+/// the database, returning the number of cells read.
+pub fn import_file(
+    connection: &mut PgConnection,
+    filename: &str,
+    area: &str,
+    validation: &ValidationConfig,
+    now: &DateTime<Tz>,
+) -> eyre::Result<usize> {
+    let mut matrix = decode_file(filename, area)?;
+    let mut count = 0;
+    for column in matrix.iter_mut().flatten() {
+        for cell in column.cells.0.iter_mut() {
+            cell.source = Some(IMPORT_SOURCE.to_owned());
+            count += 1;
+        }
+    }
+    let revised = price_matrix::insert_matrix_to_database(connection, &matrix, validation, now)?;
+    if revised > 0 {
+        println!("{} prices were revised", revised);
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Datelike, TimeZone};
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn decodes_csv_with_header() {
+        let matrix = decode_file("samples/prices.csv", "EE").unwrap();
+        assert!(matrix.len() == 1);
+        let column = matrix[0].as_ref().unwrap();
+        assert!(column.date.day() == 31);
+        assert!(column.cells.0.len() == 23);
+        assert!(column.is_complete());
+        let first = &column.cells.0[0];
+        assert!(first.moment == MARKET_TZ.with_ymd_and_hms(2024, 3, 31, 0, 0, 0).unwrap());
+        assert!(first.price == PricePerMwh(dec!(92.68)));
+        assert!(first.interval == Duration::hours(1));
+        assert!(first.area == "EE");
+    }
+
+    #[test]
+    fn decodes_semicolon_csv() {
+        let matrix = decode_file("samples/prices_quarter.csv", "EE").unwrap();
+        let column = matrix[0].as_ref().unwrap();
+        assert!(column.cells.0.len() == 8);
+        let cell = &column.cells.0[1];
+        assert!(cell.interval == Duration::minutes(15));
+        assert!(cell.price == PricePerMwh(dec!(59.40)));
+        assert!(cell.moment == MARKET_TZ.with_ymd_and_hms(2024, 10, 10, 0, 15, 0).unwrap());
+    }
+
+    #[test]
+    fn keeps_resolution_of_each_day() {
+        let body = "2025-09-30 22:00,40\n2025-09-30 23:00,41\n\
+                    2025-10-01 00:00,42\n2025-10-01 00:15,43\n2025-10-01 00:30,44\n";
+        let matrix = decode_csv(body, "EE").unwrap();
+        let hourly = &matrix[0].as_ref().unwrap().cells.0;
+        assert!(hourly.iter().all(|cell| cell.interval == Duration::hours(1)));
+        let quarters = &matrix[1].as_ref().unwrap().cells.0;
+        assert!(quarters.iter().all(|cell| cell.interval == Duration::minutes(15)));
+    }
+
+    #[test]
+    fn tells_repeated_hour_apart() {
+        let body = "2024-10-27 01:00,10\n2024-10-27 02:00,20\n\
+                    2024-10-27 02:00,21\n2024-10-27 03:00,30\n";
+        let matrix = decode_csv(body, "EE").unwrap();
+        let cells = &matrix[0].as_ref().unwrap().cells.0;
+        assert!(cells.len() == 4);
+        assert!(cells[1].price == PricePerMwh(dec!(20)));
+        assert!(cells[2].price == PricePerMwh(dec!(21)));
+        assert!(cells[2].moment - cells[1].moment == Duration::hours(1));
+        assert!(cells.iter().all(|cell| cell.interval == Duration::hours(1)));
+    }
+
+    #[test]
+    fn rejects_broken_rows() {
+        let body = "2024-10-10 00:00,12.5\n2024-10-10 01:00,twelve\n";
+        assert!(decode_csv(body, "EE").is_err());
+        let body = "2024-10-10 00:00\n";
+        assert!(decode_csv(body, "EE").is_err());
+    }
+
+    #[test]
+    fn detects_file_formats() {
+        let legacy = decode_file("samples/request.json", "EE").unwrap();
+        assert!(!legacy.is_empty());
+        let portal = decode_file("samples/dataportal_request.json", "EE").unwrap();
+        assert!(portal[0].as_ref().unwrap().cells.0.len() == 24);
+        let entsoe = decode_file("samples/entsoe_a44.xml", "EE").unwrap();
+        assert!(entsoe.len() == 2);
+        assert!(decode_file("samples/default.toml", "EE").is_err());
+    }
+
+    #[test]
+    fn keeps_entsoe_area() {
+        let portal = decode_file("samples/dataportal_request.json", "FI").unwrap();
+        assert!(portal[0].as_ref().unwrap().cells.0[0].area == "FI");
+        let entsoe = decode_file("samples/entsoe_a44.xml", "FI").unwrap();
+        assert!(entsoe[0].as_ref().unwrap().cells.0[0].area == "EE");
+    }
+}
@@ -3,7 +3,10 @@ use chrono_tz::Tz;
 use diesel::PgConnection;
 use rust_decimal::Decimal;
 
-use crate::price_cell::PriceCell;
+use crate::{
+    price_cell::PriceCell,
+    price_validation::{self, ValidationConfig},
+};
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct PricePerMwh(pub Decimal);
@@ -45,6 +48,28 @@ impl DateColumn {
     pub fn new(date: Date<Tz>) -> DateColumn {
         DateColumn { date, cells: DaySlice(vec![]) }
     }
+
+    /// Whether the cells cover the whole day from midnight to
+    /// midnight without gaps or overlaps, accounting for 23 and
+    /// 25 hour days.
+    pub fn is_complete(&self) -> bool {
+        let start = self.date.and_hms_opt(0, 0, 0);
+        let end = self.date.succ_opt().and_then(|d| d.and_hms_opt(0, 0, 0));
+        let (Some(start), Some(end)) = (start, end) else {
+            return false;
+        };
+
+        let mut cells: Vec<_> = self.cells.0.iter().collect();
+        cells.sort_by_key(|cell| cell.moment);
+        let mut cursor = start;
+        for cell in cells {
+            if cell.moment != cursor {
+                return false;
+            }
+            cursor = cell.end();
+        }
+        cursor == end
+    }
 }
 
 pub type PriceMatrix = Vec<Option<DateColumn>>;
@@ -82,20 +107,51 @@ pub fn truncate_to_24_hours(day_prices: &DaySlice) -> DaySlice {
     DaySlice(vec)
 }
 
+/// Splits every price cell longer than the given interval into
+/// cells of that interval carrying the same price. Cells that are
+/// already as fine or finer are kept as they are.
+pub fn expand_to_interval(day_prices: &DaySlice, interval: Duration) -> DaySlice {
+    let mut vec = vec![];
+    for cell in &day_prices.0 {
+        if cell.interval <= interval {
+            vec.push(cell.clone());
+            continue;
+        }
+        let mut moment = cell.moment;
+        while moment < cell.end() {
+            vec.push(PriceCell {
+                moment,
+                interval,
+                ..cell.clone()
+            });
+            moment += interval;
+        }
+    }
+    DaySlice(vec)
+}
+
+/// Inserts or revises every price in the matrix, returning
+/// the number of revised prices. Days failing validation are
+/// left out and reported on stderr and in convars.
 pub fn insert_matrix_to_database(
     connection: &mut PgConnection,
     date_matrix: &PriceMatrix,
-) -> eyre::Result<()> {
-    let date_matrix = date_matrix
+    validation: &ValidationConfig,
+    now: &DateTime<Tz>,
+) -> eyre::Result<usize> {
+    let (date_matrix, report) = price_validation::validate_matrix(date_matrix, validation);
+    if !report.is_empty() {
+        eprint!("{}", report);
+    }
+    price_validation::record_validation(connection, &report)?;
+
+    let cells = date_matrix
         .iter()
-        .filter_map(|o| o.as_ref());
+        .filter_map(|o| o.as_ref())
+        .flat_map(|date| date.cells.0.iter().cloned())
+        .collect();
 
-    for date in date_matrix {
-        for price in &date.cells.0 {
-            price.insert_cell_into_database(connection)?;
-        }
-    }
-    Ok(())
+    PriceCell::insert_cells_into_database(connection, &cells, now)
 }
 
 #[cfg(test)]
@@ -103,7 +159,12 @@ mod tests {
     use chrono::TimeZone;
     use rand::thread_rng;
 
-    use crate::{constants::MARKET_TZ, sample_data::tests::sample_day};
+    use crate::{
+        constants::MARKET_TZ,
+        nord_pool_meta::parse_date,
+        sample_data::tests::{sample_day, sample_day_specified},
+        sample_data::SAMPLE_DAY_PRICES_PARTIAL,
+    };
 
     use super::*;
 
@@ -136,4 +197,49 @@ mod tests {
         let truncated = truncate_to_24_hours(&sample_day);
         assert!(truncated.0.len() == 24);
     }
+
+    #[test]
+    fn expands_hours_to_quarters() {
+        let sample_day = sample_day_specified(&SAMPLE_DAY_PRICES_PARTIAL, 0);
+        let expanded = expand_to_interval(&sample_day, Duration::minutes(15));
+        assert!(expanded.0.len() == 32);
+        assert!(expanded.0[4].moment == sample_day.0[1].moment);
+        assert!(expanded.0[5].moment == sample_day.0[1].moment + Duration::minutes(15));
+        assert!(expanded.0[5].price == sample_day.0[1].price);
+        assert!(expanded.0[5].market_hour == sample_day.0[1].market_hour);
+        assert!(expanded.0[5].interval == Duration::minutes(15));
+    }
+
+    #[test]
+    fn checks_complete_days() {
+        let date = MARKET_TZ.with_ymd_and_hms(2022, 3, 3, 0, 0, 0).earliest().unwrap();
+        let mut column = DateColumn::new(parse_date("03-03-2022", &MARKET_TZ).unwrap());
+        column.cells = sample_day(&date, 0, 24, &mut rand::rng()).unwrap();
+        assert!(column.is_complete());
+        column.cells.0.remove(7);
+        assert!(!column.is_complete());
+
+        column.cells = sample_day(&date, 0, 25, &mut rand::rng()).unwrap();
+        assert!(!column.is_complete());
+    }
+
+    #[test]
+    fn checks_complete_dst_days() {
+        let date = MARKET_TZ.with_ymd_and_hms(2022, 10, 30, 0, 0, 0).earliest().unwrap();
+        let mut column = DateColumn::new(parse_date("30-10-2022", &MARKET_TZ).unwrap());
+        column.cells = sample_day(&date, 0, 24, &mut rand::rng()).unwrap();
+        assert!(!column.is_complete());
+        column.cells = sample_day(&date, 0, 25, &mut rand::rng()).unwrap();
+        assert!(column.is_complete());
+        let quarters = expand_to_interval(&column.cells, Duration::minutes(15));
+        column.cells = quarters;
+        assert!(column.is_complete());
+    }
+
+    #[test]
+    fn keeps_finer_intervals() {
+        let sample_day = sample_day_specified(&SAMPLE_DAY_PRICES_PARTIAL, 0);
+        let expanded = expand_to_interval(&sample_day, Duration::hours(1));
+        assert!(expanded.0.len() == 8);
+    }
 }
@@ -0,0 +1,144 @@
+use chrono::NaiveDate;
+use eyre::eyre;
+
+use crate::price_matrix::PriceMatrix;
+
+/// A vendor of day-ahead prices.
+pub trait PriceSource {
+    /// Name recorded with every price cell fetched from this source.
+    fn name(&self) -> &'static str;
+
+    /// Fetches today's and tomorrow's prices of a delivery area.
+    fn fetch(&self, area: &str) -> eyre::Result<PriceMatrix>;
+}
+
+fn has_complete_day(matrix: &PriceMatrix, date: &NaiveDate) -> bool {
+    matrix
+        .iter()
+        .flatten()
+        .any(|column| column.date.naive_local() == *date && column.is_complete())
+}
+
+fn set_source(matrix: &mut PriceMatrix, name: &str) {
+    for column in matrix.iter_mut().flatten() {
+        for cell in column.cells.0.iter_mut() {
+            cell.source = Some(name.to_owned());
+        }
+    }
+}
+
+/// Tries the given sources in order until one of them returns a
+/// complete day of prices in `area` for `date`. If none does, the first
+/// result that fetched successfully is used instead.
+///
+/// Every cell in the returned matrix is marked with the name
+/// of the source it came from.
+pub fn fetch_from_sources(
+    sources: &[Box<dyn PriceSource>],
+    date: &NaiveDate,
+    area: &str,
+) -> eyre::Result<PriceMatrix> {
+    let mut fallback = None;
+
+    for source in sources {
+        println!("Fetching prices from {}", source.name());
+        match source.fetch(area) {
+            Ok(mut matrix) => {
+                set_source(&mut matrix, source.name());
+                if has_complete_day(&matrix, date) {
+                    return Ok(matrix);
+                }
+                eprintln!("{} has no complete day for {}", source.name(), date);
+                if fallback.is_none() {
+                    fallback = Some(matrix);
+                }
+            }
+            Err(e) => eprintln!("{} failed: {}", source.name(), e),
+        }
+    }
+
+    fallback.ok_or(eyre!("No price source returned any prices"))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+    use crate::{
+        constants::MARKET_TZ,
+        nord_pool_meta::parse_date,
+        price_matrix::DateColumn,
+        sample_data::tests::sample_day,
+    };
+
+    struct FakeSource {
+        name: &'static str,
+        hours: Option<u32>,
+    }
+
+    impl PriceSource for FakeSource {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn fetch(&self, area: &str) -> eyre::Result<PriceMatrix> {
+            assert!(area == "EE");
+            let hours = self.hours.ok_or(eyre!("Vendor outage"))?;
+            let start = MARKET_TZ.with_ymd_and_hms(2024, 10, 10, 0, 0, 0).unwrap();
+            let mut column = DateColumn::new(parse_date("2024-10-10", &MARKET_TZ)?);
+            column.cells = sample_day(&start, 0, hours, &mut rand::rng())?;
+            Ok(vec![Some(column)])
+        }
+    }
+
+    fn target_date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 10, 10).unwrap()
+    }
+
+    fn used_source(matrix: &PriceMatrix) -> Option<String> {
+        matrix[0].as_ref().unwrap().cells.0[0].source.clone()
+    }
+
+    #[test]
+    fn skips_failing_source() {
+        let sources: Vec<Box<dyn PriceSource>> = vec![
+            Box::new(FakeSource { name: "first", hours: None }),
+            Box::new(FakeSource { name: "second", hours: Some(24) }),
+        ];
+        let matrix = fetch_from_sources(&sources, &target_date(), "EE").unwrap();
+        assert!(used_source(&matrix) == Some("second".to_owned()));
+    }
+
+    #[test]
+    fn prefers_complete_day() {
+        let sources: Vec<Box<dyn PriceSource>> = vec![
+            Box::new(FakeSource { name: "partial", hours: Some(12) }),
+            Box::new(FakeSource { name: "complete", hours: Some(24) }),
+            Box::new(FakeSource { name: "unused", hours: Some(24) }),
+        ];
+        let matrix = fetch_from_sources(&sources, &target_date(), "EE").unwrap();
+        assert!(used_source(&matrix) == Some("complete".to_owned()));
+    }
+
+    #[test]
+    fn falls_back_to_partial_day() {
+        let sources: Vec<Box<dyn PriceSource>> = vec![
+            Box::new(FakeSource { name: "partial", hours: Some(12) }),
+            Box::new(FakeSource { name: "broken", hours: None }),
+        ];
+        let matrix = fetch_from_sources(&sources, &target_date(), "EE").unwrap();
+        assert!(used_source(&matrix) == Some("partial".to_owned()));
+        let tomorrow = target_date() + Duration::days(1);
+        assert!(!has_complete_day(&matrix, &tomorrow));
+    }
+
+    #[test]
+    fn fails_when_all_sources_fail() {
+        let sources: Vec<Box<dyn PriceSource>> = vec![
+            Box::new(FakeSource { name: "first", hours: None }),
+            Box::new(FakeSource { name: "second", hours: None }),
+        ];
+        assert!(fetch_from_sources(&sources, &target_date(), "EE").is_err());
+    }
+}
@@ -0,0 +1,238 @@
+use std::fmt;
+
+use chrono::DateTime;
+use chrono_tz::Tz;
+use diesel::PgConnection;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::Deserialize;
+
+use crate::{
+    constants::{CVAR_PRICE_QUARANTINE, CVAR_PRICE_VALIDATION_FAILED},
+    convars,
+    price_matrix::{DateColumn, PriceMatrix},
+};
+
+/// Limits of plausible prices. The defaults are the harmonised
+/// minimum and maximum clearing prices of the day-ahead market.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ValidationConfig {
+    pub min_price_mwh: Option<Decimal>,
+    pub max_price_mwh: Option<Decimal>,
+    /// Whether prices below zero are expected. Defaults to true.
+    pub allow_negative: Option<bool>,
+}
+
+impl ValidationConfig {
+    fn min_price(&self) -> Decimal {
+        self.min_price_mwh.unwrap_or(dec!(-500))
+    }
+
+    fn max_price(&self) -> Decimal {
+        self.max_price_mwh.unwrap_or(dec!(4000))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PriceIssue {
+    NoCells,
+    IntervalCount { expected: i64, found: usize },
+    Duplicate(DateTime<Tz>),
+    Gap { from: DateTime<Tz>, to: DateTime<Tz> },
+    OutOfBand { moment: DateTime<Tz>, price: Decimal },
+    Negative { moment: DateTime<Tz>, price: Decimal },
+}
+
+impl fmt::Display for PriceIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PriceIssue::NoCells => write!(f, "no prices"),
+            PriceIssue::IntervalCount { expected, found } => {
+                write!(f, "{} intervals where {} were expected", found, expected)
+            }
+            PriceIssue::Duplicate(moment) => write!(f, "duplicate price at {}", moment),
+            PriceIssue::Gap { from, to } => write!(f, "no prices from {} to {}", from, to),
+            PriceIssue::OutOfBand { moment, price } => {
+                write!(f, "implausible price {} at {}", price, moment)
+            }
+            PriceIssue::Negative { moment, price } => {
+                write!(f, "unexpected negative price {} at {}", price, moment)
+            }
+        }
+    }
+}
+
+/// Checks a day of prices for completeness and plausibility.
+pub fn validate_column(column: &DateColumn, config: &ValidationConfig) -> Vec<PriceIssue> {
+    let start = column.date.and_hms_opt(0, 0, 0);
+    let end = column.date.succ_opt().and_then(|d| d.and_hms_opt(0, 0, 0));
+    let (Some(start), Some(end)) = (start, end) else {
+        return vec![PriceIssue::NoCells];
+    };
+
+    let mut cells: Vec<_> = column.cells.0.iter().collect();
+    cells.sort_by_key(|cell| cell.moment);
+    let Some(interval) = cells.iter().map(|cell| cell.interval).min() else {
+        return vec![PriceIssue::NoCells];
+    };
+
+    let mut issues = vec![];
+    let expected = (end - start).num_minutes() / interval.num_minutes().max(1);
+    if cells.len() as i64 != expected {
+        issues.push(PriceIssue::IntervalCount { expected, found: cells.len() });
+    }
+
+    let mut cursor = start;
+    for cell in &cells {
+        if cell.moment < cursor {
+            issues.push(PriceIssue::Duplicate(cell.moment));
+            continue;
+        }
+        if cell.moment > cursor {
+            issues.push(PriceIssue::Gap { from: cursor, to: cell.moment });
+        }
+        cursor = cell.end();
+    }
+    if cursor < end {
+        issues.push(PriceIssue::Gap { from: cursor, to: end });
+    }
+
+    for cell in &cells {
+        let price = cell.price.0;
+        if price < config.min_price() || price > config.max_price() {
+            issues.push(PriceIssue::OutOfBand { moment: cell.moment, price });
+        } else if price < Decimal::ZERO && !config.allow_negative.unwrap_or(true) {
+            issues.push(PriceIssue::Negative { moment: cell.moment, price });
+        }
+    }
+    issues
+}
+
+fn format_report(rejected: &[(&DateColumn, Vec<PriceIssue>)]) -> String {
+    let mut report = String::new();
+    for (column, issues) in rejected {
+        let source = column.cells.0.first().and_then(|cell| cell.source.as_deref());
+        report += &format!(
+            "Rejected prices for {} from {}:\n",
+            column.date.naive_local(),
+            source.unwrap_or("unknown source")
+        );
+        for issue in issues {
+            report += &format!("  - {}\n", issue);
+        }
+    }
+    report
+}
+
+/// Splits the matrix into valid days and a report of the rejected
+/// ones. The report is empty if every day passed.
+pub fn validate_matrix(matrix: &PriceMatrix, config: &ValidationConfig) -> (PriceMatrix, String) {
+    let mut accepted = vec![];
+    let mut rejected = vec![];
+    for column in matrix.iter().flatten() {
+        let issues = validate_column(column, config);
+        if issues.is_empty() {
+            accepted.push(Some(column.clone()));
+        } else {
+            rejected.push((column, issues));
+        }
+    }
+    (accepted, format_report(&rejected))
+}
+
+/// Records the outcome of a validation in convars: a failure flag
+/// and, for rejected days, the report of the last quarantine.
+pub fn record_validation(connection: &mut PgConnection, report: &str) -> eyre::Result<()> {
+    convars::set_int(connection, CVAR_PRICE_VALIDATION_FAILED, i32::from(!report.is_empty()))?;
+    if !report.is_empty() {
+        convars::set_string(connection, CVAR_PRICE_QUARANTINE, report)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+    use crate::{
+        constants::MARKET_TZ,
+        nord_pool_meta::parse_date,
+        price_matrix::PricePerMwh,
+        sample_data::tests::sample_day,
+    };
+
+    fn sample_column(date: &str, hours: u32) -> DateColumn {
+        let mut column = DateColumn::new(parse_date(date, &MARKET_TZ).unwrap());
+        let start = column.date.and_hms_opt(0, 0, 0).unwrap();
+        column.cells = sample_day(&start, 0, hours, &mut rand::rng()).unwrap();
+        column
+    }
+
+    #[test]
+    fn accepts_complete_day() {
+        let column = sample_column("2024-10-10", 24);
+        assert!(validate_column(&column, &ValidationConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn finds_missing_hours() {
+        let mut column = sample_column("2024-10-10", 24);
+        column.cells.0.remove(5);
+        let issues = validate_column(&column, &ValidationConfig::default());
+        let from = MARKET_TZ.with_ymd_and_hms(2024, 10, 10, 5, 0, 0).unwrap();
+        assert!(issues.contains(&PriceIssue::IntervalCount { expected: 24, found: 23 }));
+        assert!(issues.contains(&PriceIssue::Gap { from, to: from + Duration::hours(1) }));
+    }
+
+    #[test]
+    fn finds_duplicates() {
+        let mut column = sample_column("2024-10-10", 24);
+        let copy = column.cells.0[7].clone();
+        column.cells.0.push(copy.clone());
+        let issues = validate_column(&column, &ValidationConfig::default());
+        assert!(issues.contains(&PriceIssue::Duplicate(copy.moment)));
+    }
+
+    #[test]
+    fn counts_dst_day() {
+        let body = std::fs::read_to_string("samples/dataportal_request_dst.json").unwrap();
+        let matrix = crate::nord_pool_data_portal::decode_json(&body, "EE").unwrap();
+        let column = matrix[0].as_ref().unwrap();
+        assert!(validate_column(column, &ValidationConfig::default()).is_empty());
+
+        let mut short = column.clone();
+        short.cells.0.truncate(24);
+        let issues = validate_column(&short, &ValidationConfig::default());
+        assert!(issues.contains(&PriceIssue::IntervalCount { expected: 25, found: 24 }));
+    }
+
+    #[test]
+    fn checks_price_band() {
+        let mut column = sample_column("2024-10-10", 24);
+        column.cells.0[2].price = PricePerMwh(dec!(9999));
+        column.cells.0[3].price = PricePerMwh(dec!(-3.5));
+        let issues = validate_column(&column, &ValidationConfig::default());
+        assert!(issues.len() == 1);
+
+        let config = ValidationConfig {
+            max_price_mwh: Some(dec!(10000)),
+            allow_negative: Some(false),
+            ..Default::default()
+        };
+        let issues = validate_column(&column, &config);
+        let moment = column.cells.0[3].moment;
+        assert!(issues == vec![PriceIssue::Negative { moment, price: dec!(-3.5) }]);
+    }
+
+    #[test]
+    fn splits_matrix() {
+        let good = sample_column("2024-10-10", 24);
+        let partial = sample_column("2024-10-11", 12);
+        let matrix = vec![Some(good), None, Some(partial)];
+        let (accepted, report) = validate_matrix(&matrix, &ValidationConfig::default());
+        assert!(accepted.len() == 1);
+        assert!(report.contains("Rejected prices for 2024-10-11"));
+        assert!(report.contains("12 intervals where 24 were expected"));
+    }
+}
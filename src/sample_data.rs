@@ -14,8 +14,10 @@ pub mod tests {
 
     use super::*;
     use crate::{
+        constants::{DEFAULT_AREA, LOCAL_TZ},
         price_cell::PriceCell,
         price_matrix::{DaySlice, PricePerMwh},
+        strategy::{PowerState, PriceChangeUnit},
     };
 
     fn random_price<R>(rng: &mut R) -> Decimal
@@ -47,6 +49,11 @@ pub mod tests {
                 tariff_price: None,
 
                 market_hour: (h + start_hour) % 24,
+                interval: Duration::hours(1),
+                source: None,
+                area: DEFAULT_AREA.to_owned(),
+                estimated: false,
+                breakdown: None,
             };
             vec.push(hour);
         }
@@ -54,7 +61,7 @@ pub mod tests {
     }
 
     /// Produces sample day based on static array of decimals.
-    /// Tariff will be None
+    /// Tariff will be None. Start hour 0 is local 1:00.
     pub fn sample_day_specified(prices: &'static [Decimal], start_hour: u32) -> DaySlice {
         let mut vec = vec![];
         let start_dt = Berlin.ymd(2022, 3, 21).and_hms(start_hour, 0, 0);
@@ -66,10 +73,25 @@ pub mod tests {
                 moment,
                 tariff_price: None,
                 market_hour: start_hour + offset,
+                interval: Duration::hours(1),
+                source: None,
+                area: DEFAULT_AREA.to_owned(),
+                estimated: false,
+                breakdown: None,
             });
         }
         DaySlice(vec)
     }
+
+    /// Local hours of the units planned on.
+    pub fn on_hours(result: &[PriceChangeUnit]) -> Vec<u32> {
+        result
+            .iter()
+            .filter(|pcu| pcu.state == PowerState::On)
+            .map(|pcu| pcu.moment.with_timezone(&LOCAL_TZ).hour())
+            .collect()
+    }
+
     #[test]
     fn random_price_in_range() {
         let mut rng = StdRng::seed_from_u64(711);
@@ -35,6 +35,9 @@ diesel::table! {
         state -> Int4,
         configuration_id -> Nullable<Int4>,
         created_at -> Timestamptz,
+        interval_minutes -> Int2,
+        stale -> Bool,
+        reasons -> Text,
     }
 }
 
@@ -43,9 +46,23 @@ diesel::table! {
         id -> Int4,
         price_mwh -> Numeric,
         moment_utc -> Timestamptz,
-        tariff_mwh -> Nullable<Numeric>,
         market_hour -> Int2,
         created_at -> Timestamptz,
+        interval_minutes -> Int2,
+        source -> Nullable<Text>,
+        area -> Text,
+    }
+}
+
+diesel::table! {
+    price_revisions (id) {
+        id -> Int4,
+        price_cell_id -> Int4,
+        old_price_mwh -> Numeric,
+        new_price_mwh -> Numeric,
+        old_source -> Nullable<Text>,
+        new_source -> Nullable<Text>,
+        created_at -> Timestamptz,
     }
 }
 
@@ -57,11 +74,26 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    tariff_prices (id) {
+        id -> Int4,
+        valid_from -> Timestamptz,
+        created_at -> Timestamptz,
+        package -> Text,
+        band -> Text,
+        cents_kwh -> Numeric,
+    }
+}
+
+diesel::joinable!(price_revisions -> price_cells (price_cell_id));
+
 diesel::allow_tables_to_appear_in_same_query!(
     convar_ints,
     convar_strings,
     day_configurations,
     power_states,
     price_cells,
+    price_revisions,
     switch_records,
+    tariff_prices,
 );
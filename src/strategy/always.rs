@@ -14,6 +14,10 @@ impl HourStrategy for AlwaysOnStrategy {
     fn plan_hour(&self, _: &DateTime<Tz>) -> PowerState {
         PowerState::On
     }
+
+    fn reason(&self, _: &DateTime<Tz>) -> String {
+        "base plan always on".to_owned()
+    }
     
     fn plan_day<'a>(&self, day_prices: &'a DaySlice) -> Vec<PriceChangeUnit<'a>> {
         day_prices
@@ -23,6 +27,9 @@ impl HourStrategy for AlwaysOnStrategy {
                 price: Some(price),
                 state: PowerState::On,
                 moment: price.moment,
+                interval: price.interval,
+                reasons: vec![self.reason(&price.moment)],
+                held_off: false,
             })
             .collect()
     }
@@ -35,6 +42,10 @@ impl HourStrategy for AlwaysOffStrategy {
     fn plan_hour(&self, _: &DateTime<Tz>) -> PowerState {
         PowerState::Off
     }
+
+    fn reason(&self, _: &DateTime<Tz>) -> String {
+        "base plan always off".to_owned()
+    }
     fn plan_day<'a>(&self, day_prices: &'a DaySlice) -> Vec<PriceChangeUnit<'a>> {
         day_prices
             .0
@@ -43,6 +54,9 @@ impl HourStrategy for AlwaysOffStrategy {
                 price: Some(price),
                 state: PowerState::Off,
                 moment: price.moment,
+                interval: price.interval,
+                reasons: vec![self.reason(&price.moment)],
+                held_off: false,
             })
             .collect()
     }
@@ -0,0 +1,157 @@
+use chrono::Duration;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use super::{in_local_hours, MaskablePowerStrategy, PowerState, PriceChangeUnit};
+use crate::price_components::PriceBasis;
+
+/// Runs a single block of consecutive hours where it costs the
+/// least, for loads such as a washing machine that cannot be
+/// interrupted. Everything outside the block is turned off.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct BlockStrategy {
+    block_hours: u8,
+    /// Local hour the block may start at. A range wrapping past
+    /// midnight allows the late and early hours of the planned day,
+    /// the block itself never runs into the next day.
+    from_hour: Option<u32>,
+    /// Local hour the block has to end by.
+    to_hour: Option<u32>,
+    /// Price the block is chosen by.
+    price_basis: Option<PriceBasis>,
+}
+
+impl BlockStrategy {
+    /// Index of the first and last unit of the cheapest block, if any
+    /// run of available, adjacent units in the allowed range is long enough.
+    fn cheapest_block(&self, changes: &[PriceChangeUnit]) -> Option<(usize, usize)> {
+        let length = Duration::hours(self.block_hours.into());
+        let basis = self.price_basis.unwrap_or_default();
+        let mut best: Option<(Decimal, usize, usize)> = None;
+        for start in 0..changes.len() {
+            let mut covered = Duration::zero();
+            let mut cost = Decimal::ZERO;
+            for (end, pcu) in changes.iter().enumerate().skip(start) {
+                let adjacent = end == start
+                    || changes[end - 1].moment + changes[end - 1].interval == pcu.moment;
+                let Some(price) = pcu.price.filter(|_| !pcu.held_off) else { break };
+                if !adjacent || !in_local_hours(&pcu.moment, self.from_hour, self.to_hour) {
+                    break;
+                }
+                cost += price.price_on(basis).0 * Decimal::from(pcu.interval.num_minutes());
+                covered += pcu.interval;
+                if covered >= length {
+                    if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                        best = Some((cost, start, end));
+                    }
+                    break;
+                }
+            }
+        }
+        best.map(|(_, start, end)| (start, end))
+    }
+}
+
+impl MaskablePowerStrategy for BlockStrategy {
+    fn plan_day_masked<'a>(&self, changes: &[PriceChangeUnit<'a>]) -> Vec<PriceChangeUnit<'a>> {
+        let mut sorted = changes.to_vec();
+        sorted.sort_by_key(|pcu| pcu.moment);
+        let block = match self.block_hours {
+            0 => None,
+            _ => match self.cheapest_block(&sorted) {
+                Some(block) => Some(block),
+                None => {
+                    eprintln!("No {} hour block with prices found", self.block_hours);
+                    return changes.to_vec();
+                }
+            },
+        };
+        let reason = format!("Block: cheapest {} hour block", self.block_hours);
+        sorted
+            .iter()
+            .enumerate()
+            .map(|(i, pcu)| {
+                if pcu.held_off {
+                    pcu.clone()
+                } else if block.is_some_and(|(start, end)| (start..=end).contains(&i)) {
+                    pcu.clone_with_reason(PowerState::On, reason.clone())
+                } else {
+                    pcu.clone_with_reason(PowerState::Off, "Block: outside block".to_owned())
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::{
+        price_matrix::expand_to_interval,
+        sample_data::tests::{on_hours, sample_day_specified},
+        strategy::{default::TariffStrategy, HourStrategy},
+    };
+    const PRICES: [Decimal; 12] = [
+        dec!(50), // 0
+        dec!(10), // 1
+        dec!(90), // 2
+        dec!(20), // 3
+        dec!(20), // 4
+        dec!(30), // 5
+        dec!(80), // 6
+        dec!(5),  // 7
+        dec!(5),  // 8
+        dec!(99), // 9
+        dec!(40), // 10
+        dec!(40), // 11
+    ];
+
+    fn block(block_hours: u8, from_hour: Option<u32>, to_hour: Option<u32>) -> BlockStrategy {
+        BlockStrategy { block_hours, from_hour, to_hour, price_basis: None }
+    }
+
+    #[test]
+    fn picks_cheapest_run() {
+        let day = sample_day_specified(&PRICES, 0);
+        let base = TariffStrategy::default().plan_day(&day);
+        // Local time is an hour ahead of the sample prices
+        let result = block(3, None, None).plan_day_masked(&base);
+        assert!(on_hours(&result) == vec![4, 5, 6]);
+        let result = block(2, None, None).plan_day_masked(&base);
+        assert!(on_hours(&result) == vec![8, 9]);
+    }
+
+    #[test]
+    fn stays_in_range() {
+        let day = sample_day_specified(&PRICES, 0);
+        let base = TariffStrategy::default().plan_day(&day);
+        let result = block(2, Some(10), Some(13)).plan_day_masked(&base);
+        assert!(on_hours(&result) == vec![11, 12]);
+        let result = block(4, Some(10), Some(13)).plan_day_masked(&base);
+        assert!(result.iter().zip(&base).all(|(a, b)| a.state == b.state));
+    }
+
+    #[test]
+    fn turns_all_off_without_hours() {
+        let day = sample_day_specified(&PRICES, 0);
+        let base = TariffStrategy::default().plan_day(&day);
+        let result = block(0, None, None).plan_day_masked(&base);
+        assert!(result.len() == base.len());
+        assert!(on_hours(&result).is_empty());
+    }
+
+    #[test]
+    fn works_in_quarter_hours() {
+        let day = sample_day_specified(&PRICES, 0);
+        let day = expand_to_interval(&day, Duration::minutes(15));
+        let base = TariffStrategy::default().plan_day(&day);
+        let result = block(2, None, None).plan_day_masked(&base);
+        assert!(result.iter().filter(|pcu| pcu.state == PowerState::On).count() == 8);
+        assert!(result[27].state == PowerState::Off);
+        assert!(result[28].state == PowerState::On);
+        assert!(result[35].state == PowerState::On);
+        assert!(result[36].state == PowerState::Off);
+    }
+}
@@ -0,0 +1,433 @@
+use chrono::{DateTime, Duration};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use super::{power_state_model::PowerStateDB, MaskablePowerStrategy, PowerState, PriceChangeUnit};
+use crate::constants::HOURLY_INTERVAL;
+
+/// Limits on the planned power states of a day, applied after
+/// the strategy and overrides whatever they are.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct DayConstraints {
+    /// Hours power may stay off in a row, counting the end of the
+    /// day before. The cheapest hour of a longer run is turned on.
+    pub max_off_hours: Option<u8>,
+    /// Shortest time power stays on once switched on.
+    pub min_on_minutes: Option<i64>,
+    /// Shortest time power stays off once switched off.
+    pub min_off_minutes: Option<i64>,
+    /// Most switches between on and off within the day.
+    pub max_switches: Option<usize>,
+}
+
+impl DayConstraints {
+    fn min_duration(&self, state: PowerState) -> Duration {
+        let minutes = match state {
+            PowerState::On => self.min_on_minutes,
+            PowerState::Off => self.min_off_minutes,
+        };
+        Duration::minutes(minutes.unwrap_or(0))
+    }
+
+    /// Applies every configured constraint to a day of states sorted
+    /// by moment: minimum run and rest times first, then the switch
+    /// cap, and last the longest time off, which wins over the rest.
+    /// `carried_off` is how long power was already off when the day
+    /// started.
+    pub fn apply(&self, changes: &mut [PriceChangeUnit], carried_off: Duration) {
+        if self.min_on_minutes.is_some() || self.min_off_minutes.is_some() {
+            self.lengthen_short_runs(changes);
+        }
+        if let Some(max_switches) = self.max_switches {
+            limit_switches(changes, max_switches);
+        }
+        if let Some(hours) = self.max_off_hours {
+            limit_off_runs(changes, Duration::hours(hours.into()), carried_off);
+        }
+    }
+}
+
+/// Constraints applied as a step of a day's pipeline, between
+/// strategies rather than after the overrides.
+pub struct ConstraintStep {
+    pub constraints: DayConstraints,
+    pub carried_off: Duration,
+}
+
+impl MaskablePowerStrategy for ConstraintStep {
+    fn plan_day_masked<'a>(&self, changes: &[PriceChangeUnit<'a>]) -> Vec<PriceChangeUnit<'a>> {
+        let mut sorted = changes.to_vec();
+        sorted.sort_by_key(|pcu| pcu.moment);
+        self.constraints.apply(&mut sorted, self.carried_off);
+        sorted
+    }
+}
+
+/// Consecutive units in the same state, `start..=end`.
+#[derive(Clone, Copy, Debug)]
+struct Run {
+    state: PowerState,
+    start: usize,
+    end: usize,
+}
+
+fn runs(changes: &[PriceChangeUnit]) -> Vec<Run> {
+    let mut runs: Vec<Run> = vec![];
+    for (i, pcu) in changes.iter().enumerate() {
+        match runs.last_mut() {
+            Some(run) if run.state == pcu.state => run.end = i,
+            _ => runs.push(Run { state: pcu.state, start: i, end: i }),
+        }
+    }
+    runs
+}
+
+fn duration(units: &[PriceChangeUnit]) -> Duration {
+    units.iter().fold(Duration::zero(), |sum, pcu| sum + pcu.interval)
+}
+
+fn day_average(changes: &[PriceChangeUnit]) -> Decimal {
+    let priced: Vec<_> = changes.iter().filter(|pcu| pcu.price.is_some()).cloned().collect();
+    average_price(&priced).unwrap_or(Decimal::ZERO)
+}
+
+/// Extra cost of switching units to the other state, taking energy
+/// bought or given up at the day's average price as free. Turning
+/// on an expensive hour costs, giving up a cheap one does too.
+fn flip_cost(units: &[PriceChangeUnit], average: Decimal) -> Decimal {
+    units
+        .iter()
+        .map(|pcu| {
+            let price = pcu.price.map(|p| p.total().0).unwrap_or(average);
+            let minutes = Decimal::from(pcu.interval.num_minutes());
+            match pcu.state {
+                PowerState::Off => (price - average) * minutes,
+                PowerState::On => (average - price) * minutes,
+            }
+        })
+        .sum()
+}
+
+fn flip(units: &mut [PriceChangeUnit], reason: &str) {
+    for pcu in units {
+        let state = match pcu.state {
+            PowerState::On => PowerState::Off,
+            PowerState::Off => PowerState::On,
+        };
+        pcu.set_state(state, reason.to_owned());
+    }
+}
+
+impl DayConstraints {
+    /// Cheapest way to give a short run its minimum length: units
+    /// taken from either neighbour as long as the neighbour keeps
+    /// its own minimum, or the whole run switched over. Returns the
+    /// range of units to switch.
+    fn fix_run(&self, changes: &[PriceChangeUnit], runs: &[Run], index: usize) -> (usize, usize) {
+        let average = day_average(changes);
+        let run = runs[index];
+        let (left, right) = (runs[index - 1], runs[index + 1]);
+        let missing = self.min_duration(run.state) - duration(&changes[run.start..=run.end]);
+        let keeps_minimum = |neighbour: Run, from: usize, to: usize, edge: bool| {
+            edge || from > to || duration(&changes[from..=to]) >= self.min_duration(neighbour.state)
+        };
+
+        let mut best = (flip_cost(&changes[run.start..=run.end], average), run.start, run.end);
+        for from in (left.start..=run.start).rev() {
+            let taken_left = duration(&changes[from..run.start]);
+            let mut to = run.end;
+            while taken_left + duration(&changes[run.end + 1..=to]) < missing {
+                if to == right.end {
+                    break;
+                }
+                to += 1;
+            }
+            if taken_left + duration(&changes[run.end + 1..=to]) < missing {
+                continue;
+            }
+            let left_kept = from == 0 || keeps_minimum(left, left.start, from - 1, index == 1);
+            let right_edge = index + 2 == runs.len();
+            let right_kept = keeps_minimum(right, to + 1, right.end, right_edge);
+            if !left_kept || !right_kept {
+                continue;
+            }
+            let cost = flip_cost(&changes[from..run.start], average)
+                + flip_cost(&changes[run.end + 1..=to], average);
+            if cost < best.0 {
+                best = (cost, from, to);
+            }
+        }
+        (best.1, best.2)
+    }
+
+    /// Lengthens or removes runs shorter than their minimum, except
+    /// the first and last of the day, which go on beyond it.
+    fn lengthen_short_runs(&self, changes: &mut [PriceChangeUnit]) {
+        for _ in 0..changes.len() {
+            let runs = runs(changes);
+            let short = (1..runs.len().saturating_sub(1)).find(|&i| {
+                duration(&changes[runs[i].start..=runs[i].end]) < self.min_duration(runs[i].state)
+            });
+            let Some(index) = short else {
+                return;
+            };
+            let (from, to) = self.fix_run(changes, &runs, index);
+            let reason = match runs[index].state {
+                PowerState::On => "Constraint: minimum run time",
+                PowerState::Off => "Constraint: minimum rest time",
+            };
+            if from < runs[index].start || to > runs[index].end {
+                flip(&mut changes[from..runs[index].start], reason);
+                flip(&mut changes[runs[index].end + 1..=to], reason);
+            } else {
+                flip(&mut changes[from..=to], reason);
+            }
+        }
+    }
+}
+
+/// Removes the runs costing least to switch over until the day has
+/// at most `max_switches` switches.
+pub fn limit_switches(changes: &mut [PriceChangeUnit], max_switches: usize) {
+    let average = day_average(changes);
+    loop {
+        let runs = runs(changes);
+        if runs.len() <= max_switches + 1 || runs.len() < 3 {
+            return;
+        }
+        let cheapest = runs[1..runs.len() - 1]
+            .iter()
+            .min_by_key(|run| flip_cost(&changes[run.start..=run.end], average));
+        let Some(run) = cheapest.copied() else {
+            return;
+        };
+        let reason = format!("Constraint: at most {} switches", max_switches);
+        flip(&mut changes[run.start..=run.end], &reason);
+    }
+}
+
+/// How long power had been off up to `until` according to cached
+/// states, the latest plan of every moment first.
+pub fn trailing_off(states: &[PriceChangeUnit], until: &DateTime<Tz>) -> Duration {
+    let latest = PowerStateDB::latest_states(states);
+    let mut off = Duration::zero();
+    let mut cursor = *until;
+    for pcu in latest.iter().rev() {
+        if pcu.moment + pcu.interval != cursor || pcu.state != PowerState::Off {
+            break;
+        }
+        off += pcu.interval;
+        cursor = pcu.moment;
+    }
+    off
+}
+
+/// Average price of a range of units. Unpriced units make the
+/// range unknown, which sorts after every known price.
+fn average_price(units: &[PriceChangeUnit]) -> Option<Decimal> {
+    let mut sum = Decimal::ZERO;
+    let mut minutes = Decimal::ZERO;
+    for pcu in units {
+        let interval = Decimal::from(pcu.interval.num_minutes());
+        sum += pcu.price?.total().0 * interval;
+        minutes += interval;
+    }
+    (minutes > Decimal::ZERO).then(|| sum / minutes)
+}
+
+/// The cheapest stretch of up to an hour within `start..=end`.
+fn cheapest_hour(changes: &[PriceChangeUnit], start: usize, end: usize) -> (usize, usize) {
+    let mut best: Option<(Option<Decimal>, usize, usize)> = None;
+    for from in start..=end {
+        let mut to = from;
+        let mut covered = changes[from].interval;
+        while covered < HOURLY_INTERVAL && to < end {
+            to += 1;
+            covered += changes[to].interval;
+        }
+        if covered < HOURLY_INTERVAL && from > start {
+            break;
+        }
+        let price = average_price(&changes[from..=to]);
+        let better = match &best {
+            None => true,
+            Some((best_price, _, _)) => match (price, best_price) {
+                (Some(price), Some(best_price)) => price < *best_price,
+                (Some(_), None) => true,
+                _ => false,
+            },
+        };
+        if better {
+            best = Some((price, from, to));
+        }
+    }
+    best.map(|(_, from, to)| (from, to)).unwrap_or((start, end))
+}
+
+/// Turns power on for the cheapest hour of every run of off states
+/// longer than `max_off`.
+pub fn limit_off_runs(changes: &mut [PriceChangeUnit], max_off: Duration, carried_off: Duration) {
+    let mut run_start = 0;
+    let mut carried = carried_off;
+    let mut i = 0;
+    while i < changes.len() {
+        let previous_end = i.checked_sub(1).map(|p| changes[p].moment + changes[p].interval);
+        if previous_end.is_some_and(|end| end != changes[i].moment) {
+            run_start = i;
+            carried = Duration::zero();
+        }
+        if changes[i].state == PowerState::On {
+            run_start = i + 1;
+            carried = Duration::zero();
+            i += 1;
+            continue;
+        }
+        let run = changes[run_start..=i]
+            .iter()
+            .fold(carried, |sum, pcu| sum + pcu.interval);
+        if run > max_off {
+            let (from, to) = cheapest_hour(changes, run_start, i);
+            let hours = max_off.num_hours();
+            for pcu in changes[from..=to].iter_mut() {
+                let reason = format!("Constraint: off for over {} hours", hours);
+                pcu.set_state(PowerState::On, reason);
+            }
+            println!("Off for over {} hours, on from {}", hours, changes[from].moment);
+            i = from;
+            continue;
+        }
+        i += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::{
+        price_matrix::{expand_to_interval, DaySlice},
+        sample_data::tests::sample_day_specified,
+        strategy::{always::AlwaysOffStrategy, HourStrategy},
+    };
+
+    const PRICES: [Decimal; 12] = [
+        dec!(50), // 0
+        dec!(40), // 1
+        dec!(60), // 2
+        dec!(30), // 3
+        dec!(70), // 4
+        dec!(80), // 5
+        dec!(10), // 6
+        dec!(90), // 7
+        dec!(95), // 8
+        dec!(20), // 9
+        dec!(99), // 10
+        dec!(15), // 11
+    ];
+
+    fn on_indices(changes: &[PriceChangeUnit]) -> Vec<usize> {
+        changes
+            .iter()
+            .enumerate()
+            .filter(|(_, pcu)| pcu.state == PowerState::On)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    #[test]
+    fn breaks_long_off_runs() {
+        let day = sample_day_specified(&PRICES, 0);
+        let mut plan = AlwaysOffStrategy.plan_day(&day);
+        limit_off_runs(&mut plan, Duration::hours(4), Duration::zero());
+        assert!(on_indices(&plan) == vec![3, 6, 11]);
+    }
+
+    #[test]
+    fn counts_yesterday() {
+        let day = sample_day_specified(&PRICES, 0);
+        let mut plan = AlwaysOffStrategy.plan_day(&day);
+        limit_off_runs(&mut plan, Duration::hours(4), Duration::hours(3));
+        assert!(on_indices(&plan) == vec![1, 6, 11]);
+    }
+
+    #[test]
+    fn turns_on_whole_hours() {
+        let day = sample_day_specified(&PRICES, 0);
+        let day = expand_to_interval(&day, Duration::minutes(15));
+        let mut plan = AlwaysOffStrategy.plan_day(&day);
+        limit_off_runs(&mut plan, Duration::hours(4), Duration::zero());
+        assert!(on_indices(&plan)[..4] == [12, 13, 14, 15]);
+    }
+
+    const SHORT_RUN_PRICES: [Decimal; 12] = [
+        dec!(50), // 0
+        dec!(50), // 1
+        dec!(10), // 2
+        dec!(20), // 3
+        dec!(90), // 4
+        dec!(30), // 5
+        dec!(50), // 6
+        dec!(10), // 7
+        dec!(50), // 8
+        dec!(50), // 9
+        dec!(50), // 10
+        dec!(50), // 11
+    ];
+
+    fn constraints() -> DayConstraints {
+        DayConstraints {
+            max_off_hours: None,
+            min_on_minutes: Some(120),
+            min_off_minutes: Some(120),
+            max_switches: None,
+        }
+    }
+
+    fn plan_with_on<'a>(day: &'a DaySlice, indices: &[usize]) -> Vec<PriceChangeUnit<'a>> {
+        let mut plan = AlwaysOffStrategy.plan_day(day);
+        for &i in indices {
+            plan[i].state = PowerState::On;
+        }
+        plan
+    }
+
+    #[test]
+    fn extends_short_runs_cheaply() {
+        let day = sample_day_specified(&SHORT_RUN_PRICES, 0);
+        let mut plan = plan_with_on(&day, &[2]);
+        constraints().apply(&mut plan, Duration::zero());
+        assert!(on_indices(&plan) == vec![2, 3]);
+    }
+
+    #[test]
+    fn fills_short_rests() {
+        let day = sample_day_specified(&SHORT_RUN_PRICES, 0);
+        let mut plan = plan_with_on(&day, &[5, 6, 8, 9]);
+        constraints().apply(&mut plan, Duration::zero());
+        assert!(on_indices(&plan) == vec![5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn caps_switches() {
+        let day = sample_day_specified(&SHORT_RUN_PRICES, 0);
+        let mut plan = plan_with_on(&day, &[1, 3, 5, 7, 9]);
+        limit_switches(&mut plan, 2);
+        assert!(on_indices(&plan) == vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn finds_trailing_off_time() {
+        let day = sample_day_specified(&PRICES, 0);
+        let mut plan = AlwaysOffStrategy.plan_day(&day);
+        plan[8].state = PowerState::On;
+        let end = plan[11].moment + plan[11].interval;
+        // An older plan of the same hour comes after the latest one
+        let mut older = plan[8].clone();
+        older.state = PowerState::Off;
+        plan.push(older);
+        assert!(trailing_off(&plan, &end) == Duration::hours(3));
+        assert!(trailing_off(&plan, &plan[5].moment) == Duration::zero());
+    }
+}
@@ -0,0 +1,111 @@
+use chrono::Duration;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use super::{apply_on_mask, unit_cost, MaskablePowerStrategy, PriceChangeUnit};
+use crate::price_components::PriceBasis;
+
+/// Keeps the expected spend of the day on the device under
+/// `max_eur`, turning power on in the cheapest intervals until the
+/// money runs out. The cheapest `min_hours` are on whatever they
+/// cost.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CostCapStrategy {
+    max_eur: Decimal,
+    min_hours: Option<u8>,
+    /// Price the spend is counted in.
+    price_basis: Option<PriceBasis>,
+    /// Power the device draws, set from the configuration.
+    #[serde(skip)]
+    pub power_kw: Option<Decimal>,
+}
+
+impl CostCapStrategy {
+    pub fn price_basis(&self) -> PriceBasis {
+        self.price_basis.unwrap_or_default()
+    }
+}
+
+impl MaskablePowerStrategy for CostCapStrategy {
+    fn plan_day_masked<'a>(&self, changes: &[PriceChangeUnit<'a>]) -> Vec<PriceChangeUnit<'a>> {
+        let Some(power_kw) = self.power_kw.filter(|kw| *kw > Decimal::ZERO) else {
+            eprintln!("No device power_kw configured for the CostCap strategy");
+            return changes.to_vec();
+        };
+        let basis = self.price_basis();
+        let cost = |i: &usize| unit_cost(&changes[*i], power_kw, basis);
+        let mut by_price: Vec<usize> = (0..changes.len())
+            .filter(|i| changes[*i].is_available() && cost(i).is_some())
+            .collect();
+        by_price.sort_by_key(|i| changes[*i].price.map(|cell| cell.price_on(basis).0));
+
+        let mut on = vec![false; changes.len()];
+        let mut floor = Duration::hours(self.min_hours.unwrap_or(0).into());
+        let mut spent = Decimal::ZERO;
+        for index in by_price {
+            let unit_cost = cost(&index).unwrap_or_default();
+            if floor <= Duration::zero() && spent + unit_cost > self.max_eur {
+                break;
+            }
+            on[index] = true;
+            spent += unit_cost;
+            floor -= changes[index].interval;
+        }
+        if spent > self.max_eur {
+            eprintln!("Minimum hours cost {} €, over the cap of {} €", spent, self.max_eur);
+        }
+
+        let on_reason = format!("CostCap: within {} €", self.max_eur);
+        let off_reason = format!("CostCap: over {} €", self.max_eur);
+        apply_on_mask(changes, &on, &on_reason, &off_reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::{
+        sample_data::tests::{on_hours, sample_day_specified},
+        strategy::{default::TariffStrategy, projected_cost, HourStrategy},
+    };
+
+    const PRICES: [Decimal; 6] = [
+        dec!(100), // 1
+        dec!(50),  // 2
+        dec!(250), // 3
+        dec!(-20), // 4
+        dec!(150), // 5
+        dec!(200), // 6
+    ];
+
+    fn cap(max_eur: Decimal, min_hours: Option<u8>) -> CostCapStrategy {
+        CostCapStrategy {
+            max_eur,
+            min_hours,
+            price_basis: None,
+            power_kw: Some(dec!(2)),
+        }
+    }
+
+    #[test]
+    fn spends_up_to_cap() {
+        let day = sample_day_specified(&PRICES, 0);
+        let base = TariffStrategy::default().plan_day(&day);
+        // At 2 kW the hours cost -0.04, 0.1, 0.2 and 0.3 € in turn
+        let result = cap(dec!(0.3), None).plan_day_masked(&base);
+        assert!(on_hours(&result) == vec![1, 2, 4]);
+        assert!(projected_cost(&result, dec!(2), PriceBasis::Network) == dec!(0.26));
+    }
+
+    #[test]
+    fn keeps_minimum_hours() {
+        let day = sample_day_specified(&PRICES, 0);
+        let base = TariffStrategy::default().plan_day(&day);
+        let result = cap(dec!(0), Some(2)).plan_day_masked(&base);
+        assert!(on_hours(&result) == vec![2, 4]);
+        let result = cap(dec!(0.6), Some(1)).plan_day_masked(&base);
+        assert!(on_hours(&result) == vec![1, 2, 4, 5]);
+    }
+}
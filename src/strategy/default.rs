@@ -3,24 +3,30 @@ use chrono_tz::Tz;
 use serde::Deserialize;
 
 use super::{HourStrategy, PowerState, PriceChangeUnit};
-use crate::{price_matrix::DaySlice, tariff::Tariff};
+use crate::{price_matrix::DaySlice, tariff::TariffPackage};
 
-#[derive(Clone, Copy, Debug, Deserialize)]
-pub struct TariffStrategy;
-
-impl TariffStrategy {
-    fn tariff_to_power_state(tariff: Tariff) -> PowerState {
-        match tariff {
-            Tariff::Night => PowerState::On,
-            Tariff::Day => PowerState::Off,
-        }
-    }
+/// Turns power on in the off-peak bands of the configured
+/// tariff package and off otherwise.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct TariffStrategy {
+    #[serde(skip)]
+    pub package: TariffPackage,
 }
 
 impl HourStrategy for TariffStrategy {
     fn plan_hour(&self, datetime: &DateTime<Tz>) -> PowerState {
-        let tariff = Tariff::get_tariff(datetime);
-        TariffStrategy::tariff_to_power_state(tariff)
+        match self.package.band_at(datetime) {
+            Some(band) if band.is_off_peak() => PowerState::On,
+            _ => PowerState::Off,
+        }
+    }
+
+    fn reason(&self, datetime: &DateTime<Tz>) -> String {
+        match self.package.band_at(datetime) {
+            Some(band) if band.is_off_peak() => format!("base plan off-peak band {}", band.name),
+            Some(band) => format!("base plan peak band {}", band.name),
+            None => format!("base plan, no band of {}", self.package.name),
+        }
     }
     fn plan_day<'a>(&self, day_prices: &'a DaySlice) -> Vec<PriceChangeUnit<'a>> {
         day_prices.0
@@ -29,6 +35,9 @@ impl HourStrategy for TariffStrategy {
                 price: Some(price),
                 state: self.plan_hour(&price.moment),
                 moment: price.moment,
+                interval: price.interval,
+                reasons: vec![self.reason(&price.moment)],
+                held_off: false,
             })
             .collect()
     }
@@ -59,7 +68,7 @@ mod tests {
     fn makes_default_strategy() {
         let date = mmxxii_23_march();
         let sample_day = sample_day(&date, 14, 24, &mut thread_rng()).unwrap();
-        let planned_day = TariffStrategy.plan_day(&sample_day);
+        let planned_day = TariffStrategy::default().plan_day(&sample_day);
         let fourteen = date.with_hour(14).unwrap().beginning_of_hour();
         assert!(planned_day[0].moment == fourteen);
         let fifteen = date.with_hour(15).unwrap().beginning_of_hour();
@@ -75,7 +84,7 @@ mod tests {
     fn makes_default_strategy_on_saturday() {
         let date = mmxxii_19_march();
         let sample_day = sample_day(&date, 14, 24, &mut thread_rng()).unwrap();
-        let planned_day = TariffStrategy.plan_day(&sample_day);
+        let planned_day = TariffStrategy::default().plan_day(&sample_day);
         assert!(planned_day[0].state == PowerState::On);
         assert!(planned_day[1].state == PowerState::On);
         assert!(planned_day[10].state == PowerState::On);
@@ -0,0 +1,164 @@
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use super::{
+    apply_on_mask, in_local_hours, unit_energy, MaskablePowerStrategy, PriceChangeUnit,
+};
+use crate::price_components::PriceBasis;
+
+/// Energy to deliver within a range of local hours, which may wrap
+/// past midnight. A window without hours covers the whole day.
+#[derive(Clone, Debug, Deserialize)]
+pub struct EnergyWindow {
+    from_hour: Option<u32>,
+    to_hour: Option<u32>,
+    kwh: Decimal,
+}
+
+impl EnergyWindow {
+    fn contains(&self, pcu: &PriceChangeUnit) -> bool {
+        in_local_hours(&pcu.moment, self.from_hour, self.to_hour)
+    }
+}
+
+/// Turns power on in the cheapest intervals that deliver the energy
+/// targets of the day to a device drawing `power_kw`, and off in
+/// every other priced interval. With quarter-hour prices the target
+/// is met to the quarter hour.
+#[derive(Clone, Debug, Deserialize)]
+pub struct EnergyStrategy {
+    /// Energy to deliver over the whole day.
+    daily_kwh: Option<Decimal>,
+    windows: Option<Vec<EnergyWindow>>,
+    /// Price intervals are chosen by.
+    price_basis: Option<PriceBasis>,
+    /// Power the device draws, set from the configuration.
+    #[serde(skip)]
+    pub power_kw: Option<Decimal>,
+}
+
+impl EnergyStrategy {
+    fn targets(&self) -> Vec<EnergyWindow> {
+        let daily = self.daily_kwh.map(|kwh| EnergyWindow {
+            from_hour: None,
+            to_hour: None,
+            kwh,
+        });
+        self.windows.iter().flatten().cloned().chain(daily).collect()
+    }
+
+    /// Whether only a daily target is set, with no windows.
+    pub fn is_daily_only(&self) -> bool {
+        self.windows.as_ref().is_none_or(|windows| windows.is_empty())
+    }
+
+    fn delivered(
+        window: &EnergyWindow,
+        changes: &[PriceChangeUnit],
+        on: &[bool],
+        power_kw: Decimal,
+    ) -> Decimal {
+        changes
+            .iter()
+            .zip(on)
+            .filter(|(pcu, on)| **on && window.contains(pcu))
+            .map(|(pcu, _)| unit_energy(pcu, power_kw))
+            .sum()
+    }
+}
+
+impl MaskablePowerStrategy for EnergyStrategy {
+    fn plan_day_masked<'a>(&self, changes: &[PriceChangeUnit<'a>]) -> Vec<PriceChangeUnit<'a>> {
+        let Some(power_kw) = self.power_kw.filter(|kw| *kw > Decimal::ZERO) else {
+            eprintln!("No device power_kw configured for the Energy strategy");
+            return changes.to_vec();
+        };
+        let basis = self.price_basis.unwrap_or_default();
+        let mut on = vec![false; changes.len()];
+        let mut by_price: Vec<usize> = (0..changes.len())
+            .filter(|i| changes[*i].is_available())
+            .collect();
+        by_price.sort_by_key(|i| changes[*i].price.map(|cell| cell.price_on(basis).0));
+
+        // Narrow windows first, so that the energy delivered within
+        // them counts towards the wider ones.
+        let mut targets = self.targets();
+        targets.sort_by_key(|window| changes.iter().filter(|pcu| window.contains(pcu)).count());
+
+        for window in &targets {
+            for &index in &by_price {
+                if Self::delivered(window, changes, &on, power_kw) >= window.kwh {
+                    break;
+                }
+                if window.contains(&changes[index]) {
+                    on[index] = true;
+                }
+            }
+            if Self::delivered(window, changes, &on, power_kw) < window.kwh {
+                eprintln!("Unable to deliver {} kWh in window {:?}", window.kwh, window);
+            }
+        }
+
+        apply_on_mask(changes, &on, "Energy: cheapest to deliver target", "Energy: target met")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::{
+        price_matrix::expand_to_interval,
+        sample_data::tests::{on_hours, sample_day_specified},
+        strategy::{default::TariffStrategy, HourStrategy},
+    };
+
+    const PRICES: [Decimal; 8] = [
+        dec!(50), // 1
+        dec!(20), // 2
+        dec!(90), // 3
+        dec!(10), // 4
+        dec!(80), // 5
+        dec!(30), // 6
+        dec!(60), // 7
+        dec!(40), // 8
+    ];
+
+    fn energy(daily_kwh: Option<Decimal>, windows: Vec<EnergyWindow>) -> EnergyStrategy {
+        EnergyStrategy {
+            daily_kwh,
+            windows: Some(windows),
+            price_basis: None,
+            power_kw: Some(dec!(2)),
+        }
+    }
+
+    #[test]
+    fn delivers_daily_energy() {
+        let day = sample_day_specified(&PRICES, 0);
+        let base = TariffStrategy::default().plan_day(&day);
+        let result = energy(Some(dec!(6)), vec![]).plan_day_masked(&base);
+        assert!(on_hours(&result) == vec![2, 4, 6]);
+    }
+
+    #[test]
+    fn uses_partial_hours() {
+        let day = sample_day_specified(&PRICES, 0);
+        let day = expand_to_interval(&day, Duration::minutes(15));
+        let base = TariffStrategy::default().plan_day(&day);
+        let result = energy(Some(dec!(5)), vec![]).plan_day_masked(&base);
+        // Two and a half hours at 2 kW
+        assert!(on_hours(&result) == vec![2, 2, 2, 2, 4, 4, 4, 4, 6, 6]);
+    }
+
+    #[test]
+    fn fills_windows_first() {
+        let day = sample_day_specified(&PRICES, 0);
+        let base = TariffStrategy::default().plan_day(&day);
+        let evening = EnergyWindow { from_hour: Some(7), to_hour: Some(9), kwh: dec!(2) };
+        let result = energy(Some(dec!(6)), vec![evening]).plan_day_masked(&base);
+        assert!(on_hours(&result) == vec![2, 4, 8]);
+    }
+}
@@ -0,0 +1,210 @@
+use chrono::{DateTime, Duration};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use super::{PowerState, PriceChangeUnit};
+use crate::price_components::PriceBasis;
+
+const DEFAULT_HORIZON_HOURS: i64 = 36;
+
+const DEFAULT_MAX_SHIFT_HOURS: i64 = 0;
+
+const MOVED_TO_TOMORROW: &str = "Horizon: on-time moved to cheaper tomorrow";
+
+const MOVED_FROM_TODAY: &str = "Horizon: on-time moved from dearer today";
+
+const MOVED_TO_TODAY: &str = "Horizon: on-time moved to cheaper today";
+
+const MOVED_FROM_TOMORROW: &str = "Horizon: on-time moved from dearer tomorrow";
+
+/// Plans today and tomorrow together once tomorrow's prices are
+/// known, so that tonight can be traded against tomorrow morning.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct HorizonConfig {
+    /// Hours from now planned together. Defaults to 36.
+    pub hours: Option<i64>,
+    /// Hours of on-time a day may hand over to the other, which
+    /// each day's budget is then over or under by. Defaults to 0,
+    /// keeping every day's budget.
+    pub max_shift_hours: Option<i64>,
+    /// Price on-time is moved by.
+    pub price_basis: Option<PriceBasis>,
+}
+
+impl HorizonConfig {
+    pub fn length(&self) -> Duration {
+        Duration::hours(self.hours.unwrap_or(DEFAULT_HORIZON_HOURS))
+    }
+
+    pub fn max_shift(&self) -> Duration {
+        Duration::hours(self.max_shift_hours.unwrap_or(DEFAULT_MAX_SHIFT_HOURS))
+    }
+}
+
+/// Best swap of an on unit of one day for an off unit of the other
+/// of the same length, as `(saving, on index, off index)`.
+fn best_swap(
+    on_day: &[PriceChangeUnit],
+    off_day: &[PriceChangeUnit],
+    price: &impl Fn(&PriceChangeUnit) -> Option<Decimal>,
+) -> Option<(Decimal, usize, usize)> {
+    let mut best: Option<(Decimal, usize, usize)> = None;
+    for (on_index, on) in on_day.iter().enumerate() {
+        let Some(on_price) = price(on).filter(|_| on.state == PowerState::On) else {
+            continue;
+        };
+        for (off_index, off) in off_day.iter().enumerate() {
+            let Some(off_price) = price(off).filter(|_| off.state == PowerState::Off) else {
+                continue;
+            };
+            let saving = on_price - off_price;
+            if on.interval == off.interval && best.is_none_or(|(most, _, _)| saving > most) {
+                best = Some((saving, on_index, off_index));
+            }
+        }
+    }
+    best.filter(|(saving, _, _)| *saving > Decimal::ZERO)
+}
+
+/// Moves on-time across midnight from the dearest on units of one
+/// day to the cheapest off units of the other, as long as neither
+/// day's on-time changes by more than `max_shift`. Only units with
+/// known prices from `from` until `until` that the days' strategies
+/// call `shiftable` are moved.
+pub fn rebalance(
+    today: &mut [PriceChangeUnit],
+    tomorrow: &mut [PriceChangeUnit],
+    from: &DateTime<Tz>,
+    until: &DateTime<Tz>,
+    max_shift: Duration,
+    basis: PriceBasis,
+    shiftable: impl Fn(&PriceChangeUnit) -> bool,
+) {
+    let price = |pcu: &PriceChangeUnit| {
+        let in_horizon = pcu.moment + pcu.interval > *from && pcu.moment < *until;
+        pcu.price
+            .filter(|cell| in_horizon && !cell.estimated && shiftable(pcu))
+            .map(|cell| cell.price_on(basis).0)
+    };
+    // On-time moved from today to tomorrow so far
+    let mut shifted = Duration::zero();
+    loop {
+        let forward = best_swap(today, tomorrow, &price).filter(|(_, on, _)| {
+            shifted + today[*on].interval <= max_shift
+        });
+        let backward = best_swap(tomorrow, today, &price).filter(|(_, on, _)| {
+            shifted - tomorrow[*on].interval >= -max_shift
+        });
+        match (forward, backward) {
+            (Some((saving, on, off)), backward)
+                if backward.is_none_or(|(other, _, _)| saving >= other) =>
+            {
+                today[on].set_state(PowerState::Off, MOVED_TO_TOMORROW.to_owned());
+                tomorrow[off].set_state(PowerState::On, MOVED_FROM_TODAY.to_owned());
+                shifted += today[on].interval;
+            }
+            (_, Some((_, on, off))) => {
+                tomorrow[on].set_state(PowerState::Off, MOVED_TO_TODAY.to_owned());
+                today[off].set_state(PowerState::On, MOVED_FROM_TOMORROW.to_owned());
+                shifted -= tomorrow[on].interval;
+            }
+            _ => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::{
+        price_matrix::DaySlice,
+        sample_data::tests::sample_day_specified,
+        strategy::{default::TariffStrategy, HourStrategy},
+    };
+
+    const TODAY: [Decimal; 4] = [dec!(10), dec!(80), dec!(90), dec!(30)];
+
+    const TOMORROW: [Decimal; 4] = [dec!(20), dec!(5), dec!(70), dec!(60)];
+
+    fn tomorrow() -> DaySlice {
+        let mut day = sample_day_specified(&TOMORROW, 0);
+        for cell in day.0.iter_mut() {
+            cell.moment += Duration::days(1);
+        }
+        day
+    }
+
+    fn states(plan: &[PriceChangeUnit]) -> Vec<PowerState> {
+        plan.iter().map(|pcu| pcu.state).collect()
+    }
+
+    fn plan_with_on<'a>(day: &'a DaySlice, on: &[usize]) -> Vec<PriceChangeUnit<'a>> {
+        let plan = TariffStrategy::default().plan_day(day);
+        plan.iter()
+            .enumerate()
+            .map(|(i, pcu)| match on.contains(&i) {
+                true => pcu.clone_with_power_state(PowerState::On),
+                false => pcu.clone_with_power_state(PowerState::Off),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn moves_on_time_to_cheaper_day() {
+        use PowerState::{Off, On};
+        let today_prices = sample_day_specified(&TODAY, 0);
+        let tomorrow_prices = tomorrow();
+        let mut today = plan_with_on(&today_prices, &[0, 1, 2]);
+        let mut tomorrow = plan_with_on(&tomorrow_prices, &[0, 1]);
+        let from = today[0].moment;
+        let until = from + Duration::hours(36);
+        let max_shift = Duration::hours(1);
+        rebalance(&mut today, &mut tomorrow, &from, &until, max_shift, PriceBasis::Spot, |_| true);
+        // One hour may move to tomorrow, where 60 is cheaper than
+        // what today has left after its two cheapest hours.
+        assert!(states(&today) == vec![On, Off, Off, On]);
+        assert!(states(&tomorrow) == vec![On, On, Off, On]);
+    }
+
+    #[test]
+    fn keeps_units_outside_horizon() {
+        use PowerState::{Off, On};
+        let today_prices = sample_day_specified(&TODAY, 0);
+        let tomorrow_prices = tomorrow();
+        let mut today = plan_with_on(&today_prices, &[0, 1, 2]);
+        let mut tomorrow = plan_with_on(&tomorrow_prices, &[0, 1]);
+        // Every hour on today is already past
+        let from = today[3].moment;
+        let until = tomorrow[2].moment;
+        let max_shift = Duration::hours(2);
+        rebalance(&mut today, &mut tomorrow, &from, &until, max_shift, PriceBasis::Spot, |_| true);
+        assert!(states(&today) == vec![On, On, On, Off]);
+        assert!(states(&tomorrow) == vec![On, On, Off, Off]);
+        // The last hour of tomorrow stays out of reach
+        let from = today[1].moment;
+        let until = tomorrow[3].moment;
+        rebalance(&mut today, &mut tomorrow, &from, &until, max_shift, PriceBasis::Spot, |_| true);
+        assert!(states(&today) == vec![On, Off, Off, On]);
+        assert!(states(&tomorrow) == vec![On, On, On, Off]);
+    }
+
+    #[test]
+    fn keeps_units_that_are_not_shiftable() {
+        use PowerState::{Off, On};
+        let today_prices = sample_day_specified(&TODAY, 0);
+        let tomorrow_prices = tomorrow();
+        let mut today = plan_with_on(&today_prices, &[0, 1, 2]);
+        let mut tomorrow = plan_with_on(&tomorrow_prices, &[0, 1]);
+        let from = today[0].moment;
+        let until = from + Duration::hours(36);
+        let max_shift = Duration::hours(1);
+        // Tomorrow's 60 is over a limit of that day
+        let shiftable = |pcu: &PriceChangeUnit| pcu.moment != tomorrow_prices.0[3].moment;
+        rebalance(&mut today, &mut tomorrow, &from, &until, max_shift, PriceBasis::Spot, shiftable);
+        assert!(states(&today) == vec![On, Off, Off, On]);
+        assert!(states(&tomorrow) == vec![On, On, On, Off]);
+    }
+}
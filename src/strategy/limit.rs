@@ -1,32 +1,35 @@
 use rust_decimal::Decimal;
 use serde::Deserialize;
 
-use super::{MaskablePowerStrategy, PowerState, PriceChangeUnit};
+use super::{MaskablePowerStrategy, PriceChangeUnit};
+use crate::price_components::PriceBasis;
 
 #[derive(Clone, Copy, Debug, Deserialize)]
 pub struct PriceLimitStrategy {
     limit_mwh: Decimal,
+    /// Price compared against the limit.
+    price_basis: Option<PriceBasis>,
 }
 
 impl MaskablePowerStrategy for PriceLimitStrategy {
-    fn plan_day_masked<'a>(&self, mask: &'a [PriceChangeUnit]) -> Vec<PriceChangeUnit<'a>> {
+    fn plan_day_masked<'a>(&self, mask: &[PriceChangeUnit<'a>]) -> Vec<PriceChangeUnit<'a>> {
         // println!("Running PriceLimitStrategy");
         mask.iter()
             .map(|pcu| match pcu.price {
                 Some(price) => {
-                    if price.total().0 > self.limit_mwh {
+                    let price = price.price_on(self.price_basis.unwrap_or_default()).0;
+                    if price > self.limit_mwh {
                         // println!("Price is too much {}, {}", price.total().0, self.limit_mwh);
-                        PriceChangeUnit {
-                            moment: price.moment,
-                            price: pcu.price,
-                            state: PowerState::Off,
-                        }
+                        let reason = format!("Limit: {} over {} €/MWh", price, self.limit_mwh);
+                        let mut pcu = pcu.clone();
+                        pcu.hold_off(reason);
+                        pcu
                     } else {
                         // println!("Price ok");
-                        *pcu
+                        pcu.clone()
                     }
                 }
-                None => *pcu,
+                None => pcu.clone(),
             })
             .collect()
     }
@@ -37,7 +40,7 @@ mod test {
     use rust_decimal::Decimal;
     use rust_decimal_macros::dec;
 
-    use crate::{sample_data::tests::sample_day_specified, strategy::{default::TariffStrategy, HourStrategy}};
+    use crate::{sample_data::tests::sample_day_specified, strategy::{default::TariffStrategy, HourStrategy, PowerState}};
 
     use super::*;
     const SAMPLE_DAY_PRICES: [Decimal; 8] = [
@@ -54,9 +57,10 @@ mod test {
     #[test]
     fn hits_limit() {
         let sample_day = sample_day_specified(&SAMPLE_DAY_PRICES, 0);
-        let base = TariffStrategy.plan_day(&sample_day);
+        let base = TariffStrategy::default().plan_day(&sample_day);
         let strategy = PriceLimitStrategy {
             limit_mwh: dec!(150.0),
+            price_basis: None,
         };
         let result = strategy.plan_day_masked(&base);
         assert!(result[0].state == PowerState::On);
@@ -68,4 +72,18 @@ mod test {
         assert!(result[6].state == PowerState::Off);
         assert!(result[7].state == PowerState::Off);
     }
+
+    #[test]
+    fn explains_limit() {
+        let sample_day = sample_day_specified(&SAMPLE_DAY_PRICES, 0);
+        let base = TariffStrategy::default().plan_day(&sample_day);
+        let strategy = PriceLimitStrategy {
+            limit_mwh: dec!(150.0),
+            price_basis: None,
+        };
+        let result = strategy.plan_day_masked(&base);
+        assert!(result[0].reasons == base[0].reasons);
+        assert!(result[3].reasons.len() == 2);
+        assert!(result[3].reasons[1] == "Limit: 190.39 over 150.0 €/MWh");
+    }
 }
@@ -1,16 +1,29 @@
-use chrono::{DateTime, Timelike};
+use chrono::{DateTime, Duration, Timelike};
 use chrono_tz::Tz;
-use eyre::{eyre, Result};
-use now::DateTimeNow;
+use eyre::Result;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
 
-use crate::{constants::HOURS_OF_DAY, price_cell::PriceCell, price_matrix::DaySlice};
+use crate::{
+    constants::{HOURLY_INTERVAL, LOCAL_TZ},
+    price_cell::{get_day_start_end, PriceCell},
+    price_components::PriceBasis,
+    price_matrix::DaySlice,
+};
 
 pub mod always;
+pub mod block;
+pub mod constraints;
+pub mod cost_cap;
 pub mod default;
+pub mod energy;
+pub mod horizon;
 pub mod limit;
 pub mod none;
 pub mod power_state_model;
 pub mod smart;
+pub mod thermal;
+pub mod windows;
 
 // pub use default::{DefaultStrategy, DefaultStrategyExclSunday};
 
@@ -27,11 +40,18 @@ pub enum PowerState {
 //     pub state: PowerState,
 // }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct ChangeUnit<T> {
     pub moment: DateTime<Tz>,
+    pub interval: Duration,
     pub state: PowerState,
     pub price: T,
+    /// Why the unit is in its state, every planning step that
+    /// decided on it in order.
+    pub reasons: Vec<String>,
+    /// Turned off by a price cap of an earlier step, which steps
+    /// planning the day afresh leave off.
+    pub held_off: bool,
 }
 
 pub enum ChangeUnitEnum<'a> {
@@ -45,10 +65,121 @@ impl<'a> PriceChangeUnit<'a> {
     pub fn clone_with_power_state(&self, state: PowerState) -> PriceChangeUnit<'a> {
         PriceChangeUnit {
             moment: self.moment,
+            interval: self.interval,
             price: self.price,
             state,
+            reasons: self.reasons.clone(),
+            held_off: self.held_off,
         }
     }
+
+    /// A copy in the given state with a reason added to its trail.
+    pub fn clone_with_reason(&self, state: PowerState, reason: String) -> PriceChangeUnit<'a> {
+        let mut pcu = self.clone_with_power_state(state);
+        pcu.reasons.push(reason);
+        pcu
+    }
+
+    /// Sets the state with a reason added to the trail.
+    pub fn set_state(&mut self, state: PowerState, reason: String) {
+        self.state = state;
+        self.reasons.push(reason);
+    }
+
+    /// Turns the unit off for good, later steps leave it off.
+    pub fn hold_off(&mut self, reason: String) {
+        self.set_state(PowerState::Off, reason);
+        self.held_off = true;
+    }
+
+    /// Whether a step planning the day afresh may turn the unit on.
+    pub fn is_available(&self) -> bool {
+        self.price.is_some() && !self.held_off
+    }
+
+    /// Whether the given moment falls within this unit's interval.
+    pub fn contains(&self, moment: &DateTime<Tz>) -> bool {
+        self.moment <= *moment && *moment < self.moment + self.interval
+    }
+}
+
+/// The finest interval found among the given prices, or an
+/// hour if there are none.
+pub fn day_interval(day_prices: &DaySlice) -> Duration {
+    day_prices
+        .0
+        .iter()
+        .map(|cell| cell.interval)
+        .min()
+        .unwrap_or(HOURLY_INTERVAL)
+}
+
+/// Total length of the units that have a price attached.
+pub fn priced_duration(changes: &[PriceChangeUnit]) -> Duration {
+    changes
+        .iter()
+        .filter(|pcu| pcu.price.is_some())
+        .fold(Duration::zero(), |sum, pcu| sum + pcu.interval)
+}
+
+/// Whether the moment falls between the given local hours, the
+/// end excluded. The range may wrap past midnight and either end
+/// may be left open.
+pub fn in_local_hours(moment: &DateTime<Tz>, from: Option<u32>, to: Option<u32>) -> bool {
+    let hour = moment.with_timezone(&LOCAL_TZ).hour();
+    match (from, to) {
+        (Some(from), Some(to)) if from <= to => (from..to).contains(&hour),
+        (Some(from), Some(to)) => hour >= from || hour < to,
+        (Some(from), None) => hour >= from,
+        (None, Some(to)) => hour < to,
+        (None, None) => true,
+    }
+}
+
+/// Energy in kWh a device drawing `power_kw` uses over a unit.
+pub fn unit_energy(pcu: &PriceChangeUnit, power_kw: Decimal) -> Decimal {
+    power_kw * Decimal::from(pcu.interval.num_minutes()) / dec!(60)
+}
+
+/// Cost in euros of running a device drawing `power_kw` over a
+/// priced unit.
+pub fn unit_cost(pcu: &PriceChangeUnit, power_kw: Decimal, basis: PriceBasis) -> Option<Decimal> {
+    pcu.price
+        .map(|cell| unit_energy(pcu, power_kw) * cell.price_on(basis).0 / dec!(1000))
+}
+
+/// The plan for a mask of units chosen to be on: those on, every
+/// other priced unit off, and units without a price or held off as
+/// they were.
+pub fn apply_on_mask<'a>(
+    changes: &[PriceChangeUnit<'a>],
+    on: &[bool],
+    on_reason: &str,
+    off_reason: &str,
+) -> Vec<PriceChangeUnit<'a>> {
+    changes
+        .iter()
+        .zip(on)
+        .map(|(pcu, on)| match (pcu.price, on) {
+            _ if pcu.held_off => pcu.clone(),
+            (_, true) => pcu.clone_with_reason(PowerState::On, on_reason.to_owned()),
+            (Some(_), false) => pcu.clone_with_reason(PowerState::Off, off_reason.to_owned()),
+            (None, false) => pcu.clone(),
+        })
+        .collect()
+}
+
+/// Expected cost in euros of the priced units that are on.
+pub fn projected_cost(
+    changes: &[PriceChangeUnit],
+    power_kw: Decimal,
+    basis: PriceBasis,
+) -> Decimal {
+    changes
+        .iter()
+        .filter(|pcu| pcu.state == PowerState::On)
+        .filter_map(|pcu| unit_cost(pcu, power_kw, basis))
+        .sum()
 }
 
 /// A power switching strategy simple enough
@@ -58,32 +189,40 @@ impl<'a> PriceChangeUnit<'a> {
 pub trait HourStrategy {
     fn plan_hour(&self, datetime: &DateTime<Tz>) -> PowerState;
 
+    /// Why the hour is planned the way it is.
+    fn reason(&self, datetime: &DateTime<Tz>) -> String;
+
     fn plan_day<'a>(&self, day_prices: &'a DaySlice) -> Vec<PriceChangeUnit<'a>>;
 
-    /// Plans a day and fills any missing hours with the
+    /// Plans a day and fills any missing intervals with the
     /// result that the strategy provides. Takes a day
-    /// running from 0-23 hours.
+    /// running from midnight to midnight, divided into the
+    /// given resolution or the finest interval found among
+    /// the prices, whichever is shorter.
     fn plan_day_full<'a>(
         &self,
         day_prices: &'a DaySlice,
         date: &DateTime<Tz>,
+        resolution: Duration,
     ) -> Result<Vec<PriceChangeUnit<'a>>> {
         let mut vec = self.plan_day(day_prices);
-        for hour in HOURS_OF_DAY {
-            let hour: u32 = hour.into();
-            let existing = vec
-                .iter()
-                .find(|pcu| pcu.moment.with_timezone(&date.timezone()).hour() == hour);
+        let interval = day_interval(day_prices).min(resolution);
+        let (day_start, day_end) = get_day_start_end(date)?;
+        let mut moment = day_start;
+        while moment < day_end {
+            let existing = vec.iter().find(|pcu| pcu.contains(&moment));
             if existing.is_none() {
-                let moment = date.with_hour(hour).ok_or(eyre!("Unable to set hour."))?;
-                let moment = moment.beginning_of_hour();
                 let pcu = PriceChangeUnit {
                     moment,
+                    interval,
                     price: None,
                     state: self.plan_hour(&moment),
+                    reasons: vec![format!("No price, {}", self.reason(&moment))],
+                    held_off: false,
                 };
                 vec.push(pcu);
             }
+            moment += interval;
         }
         vec.sort_by(|a, b| a.moment.cmp(&b.moment));
         Ok(vec)
@@ -93,7 +232,7 @@ pub trait HourStrategy {
 /// A power switching strategy that accepts a set of
 /// already-set price changes.
 pub trait MaskablePowerStrategy {
-    fn plan_day_masked<'a>(&self, changes: &'a [PriceChangeUnit]) -> Vec<PriceChangeUnit<'a>>;
+    fn plan_day_masked<'a>(&self, changes: &[PriceChangeUnit<'a>]) -> Vec<PriceChangeUnit<'a>>;
 }
 
 #[cfg(test)]
@@ -103,7 +242,8 @@ mod tests {
     use rand::thread_rng;
 
     use crate::{
-        constants::{MARKET_TZ, PLANNING_TZ},
+        constants::PLANNING_TZ,
+        price_matrix::expand_to_interval,
         sample_data::tests::sample_day,
         strategy::{default::TariffStrategy, PowerState},
     };
@@ -113,7 +253,7 @@ mod tests {
         // let date = PLANNING_TZ.ymd(2022, 7, 14);
         let date = PLANNING_TZ.with_ymd_and_hms(2022, 7, 14, 0, 0, 0).earliest().unwrap();
         let day = sample_day(&date, 4, 12, &mut thread_rng()).unwrap();
-        let filled = TariffStrategy.plan_day_full(&day, &date).unwrap();
+        let filled = TariffStrategy::default().plan_day_full(&day, &date, HOURLY_INTERVAL).unwrap();
         assert!(filled[0].moment.hour() == 0);
         assert!(filled[0].state == PowerState::On);
         assert!(filled[4].moment.hour() == 4);
@@ -127,4 +267,38 @@ mod tests {
         assert!(filled[23].moment.hour() == 23);
         assert!(filled[23].state == PowerState::On);
     }
+
+    #[test]
+    fn fills_quarter_hour_gaps() {
+        let date = PLANNING_TZ.with_ymd_and_hms(2022, 7, 14, 0, 0, 0).earliest().unwrap();
+        let day = sample_day(&date, 4, 12, &mut rand::rng()).unwrap();
+        let day = expand_to_interval(&day, Duration::minutes(15));
+        let filled = TariffStrategy::default().plan_day_full(&day, &date, HOURLY_INTERVAL).unwrap();
+        assert!(filled.len() == 96);
+        assert!(filled[0].price.is_none());
+        assert!(filled[0].interval == Duration::minutes(15));
+        assert!(filled[17].moment.hour() == 4);
+        assert!(filled[17].moment.minute() == 15);
+        assert!(filled[17].price.is_some());
+        assert!(filled[95].moment.minute() == 45);
+    }
+
+    #[test]
+    fn fills_empty_day_at_resolution() {
+        let date = PLANNING_TZ.with_ymd_and_hms(2022, 7, 14, 0, 0, 0).earliest().unwrap();
+        let day = DaySlice(vec![]);
+        let filled = TariffStrategy::default()
+            .plan_day_full(&day, &date, Duration::minutes(15))
+            .unwrap();
+        assert!(filled.len() == 96);
+        assert!(filled[95].interval == Duration::minutes(15));
+    }
+
+    #[test]
+    fn fills_dst_day() {
+        let date = PLANNING_TZ.with_ymd_and_hms(2022, 10, 30, 0, 0, 0).earliest().unwrap();
+        let day = sample_day(&date, 4, 12, &mut rand::rng()).unwrap();
+        let filled = TariffStrategy::default().plan_day_full(&day, &date, HOURLY_INTERVAL).unwrap();
+        assert!(filled.len() == 25);
+    }
 }
@@ -1,6 +1,9 @@
 use crate::schema::power_states;
-use crate::{constants::PLANNING_TZ, price_cell::get_day_start_end};
-use chrono::{Date, DateTime, Utc};
+use crate::{
+    constants::{MAX_INTERVALS_PER_DAY, PLANNING_TZ},
+    price_cell::get_day_start_end,
+};
+use chrono::{DateTime, Duration, Utc};
 use chrono_tz::Tz;
 use diesel::{prelude::*, PgConnection};
 
@@ -13,6 +16,9 @@ pub struct PowerStateDB {
     state: i32,
     configuration_id: Option<i32>,
     created_at: DateTime<Utc>,
+    interval_minutes: i16,
+    stale: bool,
+    reasons: String,
 }
 
 impl PowerStateDB {
@@ -62,15 +68,17 @@ impl PowerStateDB {
                 .filter(moment_utc.ge(&day_start))
                 .filter(moment_utc.lt(&day_end))
                 .filter(configuration_id.eq(conf_id))
+                .filter(stale.eq(false))
                 .order_by(id.desc())
-                .limit(48)
+                .limit(2 * MAX_INTERVALS_PER_DAY)
                 .load::<PowerStateDB>(connection)?,
             None => power_states
                 .filter(moment_utc.ge(&day_start))
                 .filter(moment_utc.lt(&day_end))
                 .filter(configuration_id.is_null())
+                .filter(stale.eq(false))
                 .order_by(id.desc())
-                .limit(48)
+                .limit(2 * MAX_INTERVALS_PER_DAY)
                 .load::<PowerStateDB>(connection)?,
         };
 
@@ -78,14 +86,50 @@ impl PowerStateDB {
 
         Ok(vec)
     }
+
+    /// The latest plan of every moment of states loaded newest first,
+    /// sorted by moment.
+    pub fn latest_states<'s, 'a>(
+        states: &'s [PriceChangeUnit<'a>],
+    ) -> Vec<&'s PriceChangeUnit<'a>> {
+        let mut latest: Vec<&PriceChangeUnit> = vec![];
+        for pcu in states {
+            if !latest.iter().any(|seen| seen.moment == pcu.moment) {
+                latest.push(pcu);
+            }
+        }
+        latest.sort_by_key(|pcu| pcu.moment);
+        latest
+    }
+
+    /// Marks every cached state starting within the given range as
+    /// stale, so that the next run plans these intervals again.
+    pub fn mark_stale(
+        connection: &mut PgConnection,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> eyre::Result<usize> {
+        use crate::schema::power_states::dsl::*;
+
+        let count = diesel::update(power_states)
+            .filter(moment_utc.ge(start))
+            .filter(moment_utc.lt(end))
+            .filter(stale.eq(false))
+            .set(stale.eq(true))
+            .execute(connection)?;
+        Ok(count)
+    }
 }
 
 impl<'a> From<PowerStateDB> for PriceChangeUnit<'a> {
     fn from(psdb: PowerStateDB) -> Self {
         PriceChangeUnit {
             moment: psdb.moment_utc.with_timezone(&PLANNING_TZ),
+            interval: Duration::minutes(psdb.interval_minutes.into()),
             price: None,
             state: PowerStateDB::num_to_state(psdb.state),
+            reasons: psdb.reasons.lines().map(str::to_owned).collect(),
+            held_off: false,
         }
     }
 }
@@ -96,6 +140,8 @@ pub struct NewPowerStateDB {
     moment_utc: DateTime<Utc>,
     state: i32,
     configuration_id: Option<i32>,
+    interval_minutes: i16,
+    reasons: String,
 }
 
 impl NewPowerStateDB {
@@ -104,6 +150,8 @@ impl NewPowerStateDB {
             moment_utc: pcu.moment.with_timezone(&Utc),
             state: PowerStateDB::state_to_num(pcu.state),
             configuration_id,
+            interval_minutes: pcu.interval.num_minutes().try_into().unwrap(),
+            reasons: pcu.reasons.join("\n"),
         }
     }
 }
@@ -111,8 +159,9 @@ impl NewPowerStateDB {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::Date;
     use crate::{
-        constants::{HOURS_OF_DAY, MARKET_TZ},
+        constants::MARKET_TZ,
         database,
     };
 
@@ -129,6 +178,8 @@ mod tests {
                     .with_timezone(&Utc),
                 state: PowerStateDB::state_to_num(s),
                 configuration_id: Some(cfid),
+                interval_minutes: 60,
+                reasons: String::new(),
             })
         }
         vec
@@ -136,12 +187,14 @@ mod tests {
 
     fn day_sample_checkerboard(date: &Date<Tz>, cfid: i32) -> Vec<NewPowerStateDB> {
         let mut vec = vec![];
-        for hour in HOURS_OF_DAY {
+        for hour in 0u8..24 {
             let state = hour % 2;
             vec.push(NewPowerStateDB {
                 moment_utc: date.and_hms(hour.into(), 0, 0).with_timezone(&Utc),
                 state: state.try_into().unwrap(),
                 configuration_id: Some(cfid),
+                interval_minutes: 60,
+                reasons: String::new(),
             })
         }
         vec
@@ -163,7 +216,7 @@ mod tests {
     //     let day_date = MARKET_TZ.ymd(2022, 3, 13);
     //     insert_checkerboard(&connection, &day_date, 71);
     //     let day = PowerStateDB::get_day_from_database(&connection, &day_date, Some(71)).unwrap();
-    //     for hour in HOURS_OF_DAY {
+    //     for hour in 0u8..24 {
     //         let expected: i32 = (hour % 2).into();
     //         let index: usize = hour.into();
     //         let actual = PowerStateDB::state_to_num(day[index].state);
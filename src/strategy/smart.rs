@@ -1,18 +1,26 @@
-use chrono::{DateTime, Timelike};
+use chrono::{DateTime, Duration, Timelike};
 use chrono_tz::Tz;
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
 use serde::Deserialize;
 
-use crate::{constants::LOCAL_TZ, price_cell::PriceCell};
+use crate::{
+    cheap_power::CheapPowerConfig, constants::LOCAL_TZ, price_cell::PriceCell,
+    price_components::PriceBasis,
+};
 
-use super::{MaskablePowerStrategy, PowerState, PriceChangeUnit};
+use super::{priced_duration, MaskablePowerStrategy, PowerState, PriceChangeUnit};
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 pub struct SmartStrategy {
     hour_budget: u8,
     morning_hours: u8,
     hard_limit_mwh: Decimal,
+    /// Price hours are ranked and limited by.
+    price_basis: Option<PriceBasis>,
+    /// Cheap hours are on without counting against the budget.
+    #[serde(skip)]
+    pub cheap_power: Option<CheapPowerConfig>,
 }
 
 fn is_morning_hour(moment: &DateTime<Tz>) -> bool {
@@ -20,54 +28,78 @@ fn is_morning_hour(moment: &DateTime<Tz>) -> bool {
     (0..7).contains(&local_hour)
 }
 
-fn hours_with_prices<'a>(
-    changes: &'a [PriceChangeUnit],
-) -> impl Iterator<Item = &'a PriceChangeUnit<'a>> {
-    changes.iter().filter(|a| a.price.is_some())
-}
-
 fn prices_only<'a>(changes: &'a [PriceChangeUnit]) -> impl Iterator<Item = &'a PriceCell> {
     changes.iter().filter_map(|pcu| pcu.price)
 }
 
-fn average_price(changes: &[PriceChangeUnit]) -> Decimal {
+fn average_price(changes: &[PriceChangeUnit], basis: PriceBasis) -> Decimal {
     let prices = prices_only(changes);
-    let average = prices.fold((0, dec!(0)), |tu, price| (tu.0 + 1, tu.1 + price.total().0));
+    let average = prices.fold((0, dec!(0)), |tu, price| {
+        (tu.0 + 1, tu.1 + price.price_on(basis).0)
+    });
     average.1 / Decimal::from(average.0)
 }
 
-fn price_or_default(price_option: &Option<&PriceCell>, default: Decimal) -> Decimal {
+fn price_or_default(
+    price_option: &Option<&PriceCell>,
+    default: Decimal,
+    basis: PriceBasis,
+) -> Decimal {
     match price_option {
-        Some(price_cell) => price_cell.total().0,
+        Some(price_cell) => price_cell.price_on(basis).0,
         None => default,
     }
 }
 
-fn sort_by_price(vec: &mut [PriceChangeUnit], default: Decimal) {
+fn sort_by_price(vec: &mut [PriceChangeUnit], default: Decimal, basis: PriceBasis) {
     vec.sort_by(|a, b| {
-        price_or_default(&a.price, default).cmp(&price_or_default(&b.price, default))
+        let a = price_or_default(&a.price, default, basis);
+        a.cmp(&price_or_default(&b.price, default, basis))
     });
 }
 
-fn sort_by_price_refs(vec: &mut [&PriceChangeUnit], default: Decimal) {
+fn sort_by_price_refs(vec: &mut [&PriceChangeUnit], default: Decimal, basis: PriceBasis) {
     vec.sort_by(|a, b| {
-        price_or_default(&a.price, default).cmp(&price_or_default(&b.price, default))
+        let a = price_or_default(&a.price, default, basis);
+        a.cmp(&price_or_default(&b.price, default, basis))
     });
 }
 
+impl SmartStrategy {
+    /// Whether a unit is neither a morning hour nor over the hard
+    /// limit, and so may be switched to move on-time between days.
+    pub fn may_shift(&self, pcu: &PriceChangeUnit) -> bool {
+        let basis = self.price_basis.unwrap_or_default();
+        !is_morning_hour(&pcu.moment)
+            && pcu.price.is_some_and(|cell| cell.price_on(basis).0 <= self.hard_limit_mwh)
+    }
+}
+
 impl MaskablePowerStrategy for SmartStrategy {
-    fn plan_day_masked<'a>(&self, changes: &'a [PriceChangeUnit]) -> Vec<PriceChangeUnit<'a>> {
-        let morning_hour_count = self.morning_hours.clamp(0, 7);
-        let count_of_hours_with_prices = hours_with_prices(changes).count();
-        if count_of_hours_with_prices < 20 {
+    fn plan_day_masked<'a>(&self, changes: &[PriceChangeUnit<'a>]) -> Vec<PriceChangeUnit<'a>> {
+        let morning_budget = Duration::hours(self.morning_hours.clamp(0, 7).into());
+        if priced_duration(changes) < Duration::hours(20) {
             return changes.to_vec();
         }
 
-        let ap = average_price(changes);
-        let mut sorted_by_price = changes.to_vec();
-        sort_by_price(&mut sorted_by_price, ap);
+        let basis = self.price_basis.unwrap_or_default();
+        let ap = average_price(changes, basis);
+        let is_cheap = |pcu: &PriceChangeUnit| {
+            self.cheap_power.as_ref().is_some_and(|cheap| cheap.is_cheap_unit(pcu))
+        };
+        let mut sorted_by_price: Vec<_> = changes
+            .iter()
+            .filter(|pcu| !pcu.held_off && !is_cheap(pcu))
+            .cloned()
+            .collect();
+        sort_by_price(&mut sorted_by_price, ap, basis);
 
-        let mut result = vec![];
+        let mut result: Vec<_> = changes
+            .iter()
+            .filter(|pcu| !pcu.held_off && is_cheap(pcu))
+            .map(|pcu| pcu.clone_with_reason(PowerState::On, "Smart: cheap hour".to_owned()))
+            .chain(changes.iter().filter(|pcu| pcu.held_off).cloned())
+            .collect();
 
         let mut morning_sorted = sorted_by_price
             .iter()
@@ -76,47 +108,55 @@ impl MaskablePowerStrategy for SmartStrategy {
             .iter()
             .filter(|pcu| !is_morning_hour(&pcu.moment));
 
-        let mut morning_hours_on = 0;
+        let mut morning_considered = Duration::zero();
+        let mut morning_on = Duration::zero();
 
-        for _ in 0..morning_hour_count {
+        while morning_considered < morning_budget {
             println!("morning hour");
             let next = morning_sorted.next();
             if let Some(pcu) = next {
+                morning_considered += pcu.interval;
                 if let Some(price) = pcu.price {
-                    if price.total().0 < self.hard_limit_mwh {
+                    if price.price_on(basis).0 < self.hard_limit_mwh {
                         println!("morning hour reserved: {}", pcu.moment);
-                        result.push(pcu.clone_with_power_state(PowerState::On));
-                        morning_hours_on += 1;
+                        let reason = "Smart: cheapest morning hour".to_owned();
+                        result.push(pcu.clone_with_reason(PowerState::On, reason));
+                        morning_on += pcu.interval;
                     }
                     else {
-                        result.push(pcu.clone_with_power_state(PowerState::Off));
+                        let reason = "Smart: morning hour over hard limit".to_owned();
+                        result.push(pcu.clone_with_reason(PowerState::Off, reason));
                     }
                 }
+            } else {
+                break;
             }
         }
 
         let mut remainder: Vec<_> = morning_sorted.chain(non_morning_sorted).collect();
-        sort_by_price_refs(&mut remainder, ap);
-        let mut remainder = remainder.into_iter();
+        sort_by_price_refs(&mut remainder, ap, basis);
 
-        let remaining_on = self.hour_budget - morning_hours_on;
-
-        for _ in 0..remaining_on {
-            let next = remainder.next();
-            if let Some(pcu) = next {
-                result.push(pcu.clone_with_power_state(PowerState::On));
-            }
-        }
+        let mut remaining_on = Duration::hours(self.hour_budget.into()) - morning_on;
 
         for pcu in remainder {
-            result.push(pcu.clone_with_power_state(PowerState::Off));
+            if remaining_on > Duration::zero() {
+                let reason = format!("Smart: within budget of {} hours", self.hour_budget);
+                result.push(pcu.clone_with_reason(PowerState::On, reason));
+                remaining_on -= pcu.interval;
+            } else {
+                let reason = format!("Smart: over budget of {} hours", self.hour_budget);
+                result.push(pcu.clone_with_reason(PowerState::Off, reason));
+            }
         }
 
         // HARD LIMIT
         for pcu_mut in result.iter_mut() {
             if let Some(price) = pcu_mut.price {
-                if price.total().0 > self.hard_limit_mwh {
-                    pcu_mut.state = PowerState::Off;
+                let price = price.price_on(basis).0;
+                if price > self.hard_limit_mwh && !pcu_mut.held_off {
+                    let reason =
+                        format!("Smart: hard limit, {} over {} €/MWh", price, self.hard_limit_mwh);
+                    pcu_mut.hold_off(reason);
                 }
             }
         }
@@ -133,6 +173,7 @@ mod test {
     use rust_decimal_macros::dec;
 
     use crate::{
+        price_matrix::expand_to_interval,
         sample_data::tests::sample_day_specified,
         strategy::{default::TariffStrategy, HourStrategy},
     };
@@ -206,8 +247,8 @@ mod test {
     #[test]
     fn gets_average() {
         let sample_day = sample_day_specified(&SAMPLE_DAY_PRICES, 0);
-        let base = TariffStrategy.plan_day(&sample_day);
-        let average = average_price(&base);
+        let base = TariffStrategy::default().plan_day(&sample_day);
+        let average = average_price(&base, PriceBasis::Network);
         println!("{}", average);
         assert!(average >= dec!(90.34));
         assert!(average < dec!(90.35));
@@ -216,7 +257,7 @@ mod test {
     #[test]
     fn sorts_prices() {
         let sample_day = sample_day_specified(&SAMPLE_DAY_PRICES_FULL, 0);
-        let base = TariffStrategy.plan_day(&sample_day);
+        let base = TariffStrategy::default().plan_day(&sample_day);
         for r in &base {
             println!("{:?}", r);
         }
@@ -224,6 +265,8 @@ mod test {
             hour_budget: 7,
             morning_hours: 0,
             hard_limit_mwh: dec!(300.0),
+            price_basis: None,
+            cheap_power: None,
         };
         let result = strat.plan_day_masked(&base);
         println!("Smart: \n");
@@ -247,7 +290,7 @@ mod test {
     #[test]
     fn sorts_prices_with_morning() {
         let sample_day = sample_day_specified(&SAMPLE_DAY_PRICES_FULL, 0);
-        let base = TariffStrategy.plan_day(&sample_day);
+        let base = TariffStrategy::default().plan_day(&sample_day);
         for r in &base {
             println!("{:?}", r);
         }
@@ -255,6 +298,8 @@ mod test {
             hour_budget: 7,
             morning_hours: 2,
             hard_limit_mwh: dec!(300.0),
+            price_basis: None,
+            cheap_power: None,
         };
         let result = strat.plan_day_masked(&base);
         println!("Smart: \n");
@@ -278,7 +323,7 @@ mod test {
     #[test]
     fn sorts_prices_good_midday() {
         let sample_day = sample_day_specified(&SAMPLE_DAY_PRICES_MIDDAY_HARD, 0);
-        let base = TariffStrategy.plan_day(&sample_day);
+        let base = TariffStrategy::default().plan_day(&sample_day);
         for r in &base {
             println!("{:?}", r);
         }
@@ -286,6 +331,8 @@ mod test {
             hour_budget: 7,
             morning_hours: 5,
             hard_limit_mwh: dec!(180.0),
+            price_basis: None,
+            cheap_power: None,
         };
         let result = strat.plan_day_masked(&base);
         println!("Smart: \n");
@@ -300,7 +347,7 @@ mod test {
     #[test]
     fn obeys_hard_limit() {
         let sample_day = sample_day_specified(&SAMPLE_DAY_PRICES_FULL, 0);
-        let base = TariffStrategy.plan_day(&sample_day);
+        let base = TariffStrategy::default().plan_day(&sample_day);
         for r in &base {
             println!("{:?}", r);
         }
@@ -308,6 +355,8 @@ mod test {
             hour_budget: 7,
             morning_hours: 2,
             hard_limit_mwh: dec!(100.0),
+            price_basis: None,
+            cheap_power: None,
         };
         let result = strat.plan_day_masked(&base);
         println!("Smart: \n");
@@ -327,4 +376,76 @@ mod test {
         assert!(result[19].state == PowerState::On);
         assert!(result[23].state == PowerState::On);
     }
+
+    #[test]
+    fn budgets_quarter_hours() {
+        let sample_day = sample_day_specified(&SAMPLE_DAY_PRICES_FULL, 0);
+        let sample_day = expand_to_interval(&sample_day, Duration::minutes(15));
+        let base = TariffStrategy::default().plan_day(&sample_day);
+        let strat = SmartStrategy {
+            hour_budget: 7,
+            morning_hours: 2,
+            hard_limit_mwh: dec!(300.0),
+            price_basis: None,
+            cheap_power: None,
+        };
+        let result = strat.plan_day_masked(&base);
+        assert!(result.len() == 96);
+        let on_count = result.iter().filter(|r| r.state == PowerState::On).count();
+        assert!(on_count == 28);
+        for quarter in 0..4 {
+            assert!(result[4 + quarter].state == PowerState::On);
+            assert!(result[28 + quarter].state == PowerState::On);
+            assert!(result[72 + quarter].state == PowerState::Off);
+            assert!(result[92 + quarter].state == PowerState::On);
+        }
+    }
+
+    #[test]
+    fn leaves_cheap_hours_out_of_budget() {
+        let sample_day = sample_day_specified(&SAMPLE_DAY_PRICES_FULL, 0);
+        let base = TariffStrategy::default().plan_day(&sample_day);
+        let strat = SmartStrategy {
+            hour_budget: 7,
+            morning_hours: 0,
+            hard_limit_mwh: dec!(300.0),
+            price_basis: None,
+            cheap_power: Some(CheapPowerConfig {
+                below_mwh: Some(dec!(35)),
+                price_basis: None,
+                secondary_loads: None,
+            }),
+        };
+        let result = strat.plan_day_masked(&base);
+        // Hours 7, 15 and 23 are cheap, the budget goes to the next seven
+        let on_count = result.iter().filter(|r| r.state == PowerState::On).count();
+        assert!(on_count == 10);
+        assert!(result[9].state == PowerState::On);
+        assert!(result[20].state == PowerState::On);
+        assert!(result[22].state == PowerState::On);
+        assert!(result[23].state == PowerState::On);
+    }
+
+    #[test]
+    fn explains_states() {
+        let sample_day = sample_day_specified(&SAMPLE_DAY_PRICES_FULL, 0);
+        let base = TariffStrategy::default().plan_day(&sample_day);
+        let strat = SmartStrategy {
+            hour_budget: 7,
+            morning_hours: 2,
+            hard_limit_mwh: dec!(100.0),
+            price_basis: None,
+            cheap_power: None,
+        };
+        let result = strat.plan_day_masked(&base);
+        let last = |i: usize| result[i].reasons.last().unwrap().as_str();
+        assert!(result[1].reasons[1] == "Smart: morning hour over hard limit");
+        assert!(last(7) == "Smart: within budget of 7 hours");
+        assert!(last(9) == "Smart: over budget of 7 hours");
+        assert!(last(23) == "Smart: cheapest morning hour");
+        assert!(last(18) == "Smart: hard limit, 201.10 over 100.0 €/MWh");
+        // The base plan's reason stays first in the trail
+        assert!(result[18].reasons.len() == 3);
+        assert!(result[18].reasons[0] == base[18].reasons[0]);
+    }
 }
@@ -0,0 +1,212 @@
+use chrono::Timelike;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::Deserialize;
+
+use super::{apply_on_mask, MaskablePowerStrategy, PowerState, PriceChangeUnit};
+use crate::{constants::LOCAL_TZ, price_components::PriceBasis};
+
+/// Energy needed to warm a litre of water by a degree, in kWh.
+const WATER_KWH_PER_LITRE_KELVIN: Decimal = dec!(0.001163);
+
+const DEFAULT_MAX_TEMP: Decimal = dec!(65);
+
+const DEFAULT_COLD_WATER_TEMP: Decimal = dec!(10);
+
+/// Hot water drawn from the tank during a local hour, replaced
+/// by cold water.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct HotWaterDraw {
+    hour: u32,
+    litres: Decimal,
+}
+
+/// Simulates the tank temperature through the day and heats in
+/// the cheapest hours that keep it at or above `min_temp_c` in
+/// the hours it is needed.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ThermalStrategy {
+    heater_kw: Decimal,
+    tank_litres: Decimal,
+    /// Heat lost to the surroundings, in kW.
+    standing_loss_kw: Option<Decimal>,
+    min_temp_c: Decimal,
+    /// Temperature the heater's own thermostat stops at. Defaults to 65.
+    max_temp_c: Option<Decimal>,
+    /// Temperature at the start of the day. Defaults to the minimum.
+    start_temp_c: Option<Decimal>,
+    /// Defaults to 10.
+    cold_water_c: Option<Decimal>,
+    /// Local hours the minimum has to hold in. Defaults to every hour.
+    keep_hours: Option<Vec<u32>>,
+    draws: Option<Vec<HotWaterDraw>>,
+    /// Price heating hours are chosen by.
+    price_basis: Option<PriceBasis>,
+}
+
+impl ThermalStrategy {
+    fn price(&self, pcu: &PriceChangeUnit) -> Option<Decimal> {
+        pcu.price
+            .map(|price| price.price_on(self.price_basis.unwrap_or_default()).0)
+    }
+
+    fn is_kept(&self, pcu: &PriceChangeUnit) -> bool {
+        let hour = pcu.moment.with_timezone(&LOCAL_TZ).hour();
+        self.keep_hours.as_ref().is_none_or(|hours| hours.contains(&hour))
+    }
+
+    /// Temperature of the tank at the end of every unit.
+    fn simulate(&self, changes: &[PriceChangeUnit], on: &[bool]) -> Vec<Decimal> {
+        let max_temp = self.max_temp_c.unwrap_or(DEFAULT_MAX_TEMP);
+        let cold = self.cold_water_c.unwrap_or(DEFAULT_COLD_WATER_TEMP);
+        let capacity = self.tank_litres * WATER_KWH_PER_LITRE_KELVIN;
+        let loss = self.standing_loss_kw.unwrap_or(Decimal::ZERO);
+        let mut temp = self.start_temp_c.unwrap_or(self.min_temp_c);
+        let mut temps = vec![];
+        for (pcu, on) in changes.iter().zip(on) {
+            let hours = Decimal::from(pcu.interval.num_minutes()) / dec!(60);
+            let hour = pcu.moment.with_timezone(&LOCAL_TZ).hour();
+            let drawn: Decimal = self
+                .draws
+                .iter()
+                .flatten()
+                .filter(|draw| draw.hour == hour)
+                .map(|draw| draw.litres * hours)
+                .sum();
+            let drawn = drawn.min(self.tank_litres);
+            temp = (temp * (self.tank_litres - drawn) + cold * drawn) / self.tank_litres;
+            let heating = if *on { self.heater_kw } else { Decimal::ZERO };
+            temp += (heating - loss) * hours / capacity;
+            temp = temp.min(max_temp);
+            temps.push(temp);
+        }
+        temps
+    }
+
+    /// First kept unit the tank is below the minimum at.
+    fn first_cold(&self, changes: &[PriceChangeUnit], temps: &[Decimal]) -> Option<usize> {
+        (0..changes.len()).find(|&i| self.is_kept(&changes[i]) && temps[i] < self.min_temp_c)
+    }
+}
+
+impl MaskablePowerStrategy for ThermalStrategy {
+    fn plan_day_masked<'a>(&self, changes: &[PriceChangeUnit<'a>]) -> Vec<PriceChangeUnit<'a>> {
+        let mut sorted = changes.to_vec();
+        sorted.sort_by_key(|pcu| pcu.moment);
+        // Unpriced units keep their state and are heated with as planned.
+        let mut on: Vec<bool> = sorted
+            .iter()
+            .map(|pcu| pcu.price.is_none() && pcu.state == PowerState::On)
+            .collect();
+        let mut by_price: Vec<usize> =
+            (0..sorted.len()).filter(|i| sorted[*i].is_available()).collect();
+        by_price.sort_by_key(|i| self.price(&sorted[*i]));
+
+        let mut temps = self.simulate(&sorted, &on);
+        while let Some(cold) = self.first_cold(&sorted, &temps) {
+            // The cheapest unit up to the cold one that still warms
+            // it, heat of earlier units may be lost at the maximum.
+            let mut warmed = false;
+            for &index in by_price.iter().filter(|i| **i <= cold) {
+                if on[index] {
+                    continue;
+                }
+                on[index] = true;
+                let tried = self.simulate(&sorted, &on);
+                if tried[cold] > temps[cold] {
+                    temps = tried;
+                    warmed = true;
+                    break;
+                }
+                on[index] = false;
+            }
+            if !warmed {
+                eprintln!("Unable to keep the tank warm at {}", sorted[cold].moment);
+                break;
+            }
+        }
+
+        apply_on_mask(&sorted, &on, "Thermal: keeps the tank warm", "Thermal: tank warm enough")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::{
+        price_matrix::expand_to_interval,
+        sample_data::tests::{on_hours, sample_day_specified},
+        strategy::{default::TariffStrategy, HourStrategy},
+    };
+
+    const PRICES: [Decimal; 12] = [
+        dec!(50), // 1
+        dec!(20), // 2
+        dec!(90), // 3
+        dec!(10), // 4
+        dec!(80), // 5
+        dec!(70), // 6
+        dec!(60), // 7
+        dec!(30), // 8
+        dec!(90), // 9
+        dec!(95), // 10
+        dec!(5),  // 11
+        dec!(99), // 12
+    ];
+
+    /// A 100 litre tank with a 2.326 kW heater, which warms it
+    /// by 20 degrees an hour.
+    fn tank(draws: Vec<HotWaterDraw>, keep_hours: Option<Vec<u32>>) -> ThermalStrategy {
+        ThermalStrategy {
+            heater_kw: dec!(2.326),
+            tank_litres: dec!(100),
+            standing_loss_kw: None,
+            min_temp_c: dec!(40),
+            max_temp_c: Some(dec!(60)),
+            start_temp_c: Some(dec!(40)),
+            cold_water_c: Some(dec!(10)),
+            keep_hours,
+            draws: Some(draws),
+            price_basis: None,
+        }
+    }
+
+    #[test]
+    fn heats_before_draw() {
+        let day = sample_day_specified(&PRICES, 0);
+        let base = TariffStrategy::default().plan_day(&day);
+        // Drawing a quarter of the tank at 7:00 leaves 32.5 degrees
+        // unless it is heated beforehand.
+        let draws = vec![HotWaterDraw { hour: 7, litres: dec!(25) }];
+        let result = tank(draws, None).plan_day_masked(&base);
+        assert!(on_hours(&result) == vec![4]);
+    }
+
+    #[test]
+    fn heats_no_more_than_the_maximum_holds() {
+        let day = sample_day_specified(&PRICES, 0);
+        let base = TariffStrategy::default().plan_day(&day);
+        // The tank holds the heat of only one of the cheap early
+        // hours, so the second draw is heated for after the first.
+        let draws = vec![
+            HotWaterDraw { hour: 5, litres: dec!(25) },
+            HotWaterDraw { hour: 9, litres: dec!(25) },
+        ];
+        let result = tank(draws, None).plan_day_masked(&base);
+        assert!(on_hours(&result) == vec![4, 8]);
+    }
+
+    #[test]
+    fn only_keeps_configured_hours() {
+        let day = sample_day_specified(&PRICES, 0);
+        let day = expand_to_interval(&day, Duration::minutes(15));
+        let base = TariffStrategy::default().plan_day(&day);
+        // Cold in the morning is fine when only noon matters, so
+        // half of the cheapest hour before it is enough.
+        let draws = vec![HotWaterDraw { hour: 7, litres: dec!(25) }];
+        let result = tank(draws, Some(vec![12])).plan_day_masked(&base);
+        assert!(on_hours(&result) == vec![11, 11]);
+    }
+}
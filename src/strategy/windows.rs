@@ -0,0 +1,203 @@
+use chrono::Duration;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use super::{apply_on_mask, in_local_hours, MaskablePowerStrategy, PriceChangeUnit};
+use crate::price_components::PriceBasis;
+
+/// A range of local hours with limits on how long power is on
+/// within it. A window may wrap past midnight, and one without
+/// hours covers the whole day.
+#[derive(Clone, Debug, Deserialize)]
+pub struct HourWindow {
+    from_hour: Option<u32>,
+    to_hour: Option<u32>,
+    /// Hours power has to be on within the window.
+    min_hours: Option<u8>,
+    /// Hours power may be on within the window at most.
+    max_hours: Option<u8>,
+    /// Power stays off in the window whenever the price is above this.
+    hard_limit_mwh: Option<Decimal>,
+}
+
+impl HourWindow {
+    fn contains(&self, pcu: &PriceChangeUnit) -> bool {
+        in_local_hours(&pcu.moment, self.from_hour, self.to_hour)
+    }
+
+    fn min(&self) -> Duration {
+        Duration::hours(self.min_hours.unwrap_or(0).into())
+    }
+
+    fn max(&self) -> Option<Duration> {
+        self.max_hours.map(|hours| Duration::hours(hours.into()))
+    }
+}
+
+/// Turns power on in the cheapest hours that give every window
+/// its minimum without going over any window's maximum, and off
+/// in every other priced hour. Generalises the morning hours and
+/// hour budget of `SmartStrategy`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct WindowsStrategy {
+    windows: Vec<HourWindow>,
+    /// Price hours are chosen and capped by.
+    price_basis: Option<PriceBasis>,
+}
+
+impl WindowsStrategy {
+    fn price(&self, pcu: &PriceChangeUnit) -> Option<Decimal> {
+        pcu.price
+            .map(|price| price.price_on(self.price_basis.unwrap_or_default()).0)
+    }
+
+    /// Whether a unit is available and under the cap of every window it is in.
+    fn is_allowed(&self, pcu: &PriceChangeUnit) -> bool {
+        let Some(price) = self.price(pcu).filter(|_| !pcu.held_off) else {
+            return false;
+        };
+        self.windows
+            .iter()
+            .filter(|window| window.contains(pcu))
+            .all(|window| window.hard_limit_mwh.is_none_or(|limit| price <= limit))
+    }
+
+    fn on_duration(window: &HourWindow, changes: &[PriceChangeUnit], on: &[bool]) -> Duration {
+        changes
+            .iter()
+            .zip(on)
+            .filter(|(pcu, on)| **on && window.contains(pcu))
+            .fold(Duration::zero(), |sum, (pcu, _)| sum + pcu.interval)
+    }
+
+    /// Whether turning the unit on keeps every window it is in
+    /// within its maximum.
+    fn fits(&self, changes: &[PriceChangeUnit], on: &[bool], index: usize) -> bool {
+        let pcu = &changes[index];
+        self.windows
+            .iter()
+            .filter(|window| window.contains(pcu))
+            .all(|window| match window.max() {
+                Some(max) => Self::on_duration(window, changes, on) + pcu.interval <= max,
+                None => true,
+            })
+    }
+}
+
+impl MaskablePowerStrategy for WindowsStrategy {
+    fn plan_day_masked<'a>(&self, changes: &[PriceChangeUnit<'a>]) -> Vec<PriceChangeUnit<'a>> {
+        let mut on = vec![false; changes.len()];
+        let mut by_price: Vec<usize> = (0..changes.len())
+            .filter(|i| self.is_allowed(&changes[*i]))
+            .collect();
+        by_price.sort_by_key(|i| self.price(&changes[*i]));
+
+        // Narrow windows first, so that a wide window's minimum
+        // is counted with the hours picked for those within it.
+        let mut windows: Vec<&HourWindow> = self.windows.iter().collect();
+        windows.sort_by_key(|window| changes.iter().filter(|pcu| window.contains(pcu)).count());
+
+        for window in windows {
+            for &index in &by_price {
+                if Self::on_duration(window, changes, &on) >= window.min() {
+                    break;
+                }
+                if !on[index] && window.contains(&changes[index]) && self.fits(changes, &on, index)
+                {
+                    on[index] = true;
+                }
+            }
+            if Self::on_duration(window, changes, &on) < window.min() {
+                eprintln!("Unable to fill window {:?}", window);
+            }
+        }
+
+        apply_on_mask(changes, &on, "Windows: cheapest to fill a window", "Windows: not needed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::{
+        sample_data::tests::{on_hours, sample_day_specified},
+        strategy::{default::TariffStrategy, HourStrategy},
+    };
+
+    const PRICES: [Decimal; 23] = [
+        dec!(50), // 1
+        dec!(40), // 2
+        dec!(60), // 3
+        dec!(45), // 4
+        dec!(70), // 5
+        dec!(80), // 6
+        dec!(10), // 7
+        dec!(12), // 8
+        dec!(11), // 9
+        dec!(15), // 10
+        dec!(14), // 11
+        dec!(13), // 12
+        dec!(90), // 13
+        dec!(95), // 14
+        dec!(99), // 15
+        dec!(30), // 16
+        dec!(120), // 17
+        dec!(130), // 18
+        dec!(35), // 19
+        dec!(140), // 20
+        dec!(150), // 21
+        dec!(20), // 22
+        dec!(25), // 23
+    ];
+
+    fn window(from_hour: u32, to_hour: u32, min_hours: u8) -> HourWindow {
+        HourWindow {
+            from_hour: Some(from_hour),
+            to_hour: Some(to_hour),
+            min_hours: Some(min_hours),
+            max_hours: None,
+            hard_limit_mwh: None,
+        }
+    }
+
+    fn whole_day(hours: u8) -> HourWindow {
+        HourWindow {
+            from_hour: None,
+            to_hour: None,
+            min_hours: Some(hours),
+            max_hours: Some(hours),
+            hard_limit_mwh: None,
+        }
+    }
+
+    fn plan(windows: Vec<HourWindow>) -> Vec<u32> {
+        let day = sample_day_specified(&PRICES, 0);
+        let base = TariffStrategy::default().plan_day(&day);
+        let strategy = WindowsStrategy { windows, price_basis: None };
+        on_hours(&strategy.plan_day_masked(&base))
+    }
+
+    #[test]
+    fn fills_every_window() {
+        let hours = plan(vec![window(0, 7, 2), window(16, 22, 2), whole_day(7)]);
+        assert!(hours == vec![2, 4, 7, 8, 9, 16, 19]);
+    }
+
+    #[test]
+    fn keeps_to_budget() {
+        let hours = plan(vec![whole_day(4)]);
+        assert!(hours == vec![7, 8, 9, 12]);
+    }
+
+    #[test]
+    fn respects_caps_and_maximums() {
+        let mut evening = window(16, 22, 2);
+        evening.hard_limit_mwh = Some(dec!(32));
+        let mut morning = window(7, 13, 0);
+        morning.max_hours = Some(1);
+        let hours = plan(vec![evening, morning, whole_day(4)]);
+        assert!(hours == vec![7, 16, 22, 23]);
+    }
+}
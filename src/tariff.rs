@@ -1,30 +1,138 @@
 use chrono::{DateTime, Datelike, Timelike, Weekday};
 use chrono_tz::Tz;
+use eyre::eyre;
+use serde::Deserialize;
 
-use crate::{constants::LOCAL_TZ, holidays::is_national_holiday};
+use crate::{constants::LOCAL_TZ, holidays::HolidayCalendar};
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum Tariff {
-    Night,
-    Day,
+pub const DEFAULT_PACKAGE: &str = "DayNight";
+
+/// A time band of a network tariff package. Every rule left
+/// out matches any time.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TariffBand {
+    pub name: String,
+    /// Months of the season the band applies in, 1 to 12.
+    pub months: Option<Vec<u32>>,
+    /// Weekdays the band applies on, e.g. `"Mon"`.
+    pub weekdays: Option<Vec<String>>,
+    /// Local hour the band starts at. Bands may wrap past midnight.
+    pub from_hour: Option<u32>,
+    /// Local hour the band ends before.
+    pub to_hour: Option<u32>,
+    /// Whether the band applies on holidays. Defaults to true.
+    pub on_holidays: Option<bool>,
+    /// Whether `TariffStrategy` turns power on in this band.
+    pub off_peak: Option<bool>,
 }
 
-impl Tariff {
-    fn daytime_tariff(hour: u32) -> Tariff {
-        if !(7..22).contains(&hour) {
-            Tariff::Night
-        } else {
-            Tariff::Day
+impl TariffBand {
+    fn matches_hour(&self, hour: u32) -> bool {
+        match (self.from_hour, self.to_hour) {
+            (Some(from), Some(to)) if from <= to => (from..to).contains(&hour),
+            (Some(from), Some(to)) => hour >= from || hour < to,
+            (Some(from), None) => hour >= from,
+            (None, Some(to)) => hour < to,
+            (None, None) => true,
+        }
+    }
+
+    fn matches_weekday(&self, weekday: Weekday) -> bool {
+        match &self.weekdays {
+            Some(days) => days.iter().any(|d| d.parse::<Weekday>() == Ok(weekday)),
+            None => true,
         }
     }
 
-    pub fn get_tariff(time: &DateTime<Tz>) -> Tariff {
+    pub fn matches(&self, time: &DateTime<Tz>, holidays: &HolidayCalendar) -> bool {
         let time = time.with_timezone(&LOCAL_TZ);
-        let day = time.weekday();
-        if [Weekday::Sat, Weekday::Sun].contains(&day) || is_national_holiday(&time.date()) {
-            Tariff::Night
-        } else {
-            Tariff::daytime_tariff(time.hour())
+        let in_season = match &self.months {
+            Some(months) => months.contains(&time.month()),
+            None => true,
+        };
+        let holiday_ok =
+            self.on_holidays.unwrap_or(true) || !holidays.is_holiday(&time.date_naive());
+        in_season
+            && holiday_ok
+            && self.matches_weekday(time.weekday())
+            && self.matches_hour(time.hour())
+    }
+
+    pub fn is_off_peak(&self) -> bool {
+        self.off_peak.unwrap_or(false)
+    }
+
+    /// Rejects rules that would never match, such as a misspelt
+    /// weekday, rather than letting the moment fall through to
+    /// another band.
+    fn validate(&self) -> eyre::Result<()> {
+        for day in self.weekdays.iter().flatten() {
+            day.parse::<Weekday>()
+                .map_err(|_| eyre!("Unknown weekday {:?} in tariff band {}", day, self.name))?;
+        }
+        if let Some(month) = self.months.iter().flatten().find(|m| !(1..=12).contains(*m)) {
+            return Err(eyre!("Month {} of tariff band {} is not 1 to 12", month, self.name));
+        }
+        if self.from_hour.is_some_and(|hour| hour > 23) {
+            return Err(eyre!("from_hour of tariff band {} is over 23", self.name));
+        }
+        if self.to_hour.is_some_and(|hour| hour > 24) {
+            return Err(eyre!("to_hour of tariff band {} is over 24", self.name));
+        }
+        Ok(())
+    }
+}
+
+/// A network tariff package: bands tried in order, the first
+/// one matching a moment applies.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TariffPackage {
+    pub name: String,
+    pub bands: Vec<TariffBand>,
+    /// Holidays of bands not applying on them, set from the
+    /// holiday configuration.
+    #[serde(skip)]
+    pub holidays: HolidayCalendar,
+}
+
+impl TariffPackage {
+    pub fn band_at(&self, time: &DateTime<Tz>) -> Option<&TariffBand> {
+        self.bands.iter().find(|band| band.matches(time, &self.holidays))
+    }
+
+    pub fn validate(&self) -> eyre::Result<()> {
+        self.bands.iter().try_for_each(TariffBand::validate)
+    }
+}
+
+impl Default for TariffPackage {
+    /// Day rate on working days from 7 to 22, night rate
+    /// otherwise and on weekends and holidays.
+    fn default() -> Self {
+        let weekdays = ["Mon", "Tue", "Wed", "Thu", "Fri"];
+        TariffPackage {
+            name: DEFAULT_PACKAGE.to_owned(),
+            bands: vec![
+                TariffBand {
+                    name: "day".to_owned(),
+                    months: None,
+                    weekdays: Some(weekdays.iter().map(|d| d.to_string()).collect()),
+                    from_hour: Some(7),
+                    to_hour: Some(22),
+                    on_holidays: Some(false),
+                    off_peak: None,
+                },
+                TariffBand {
+                    name: "night".to_owned(),
+                    months: None,
+                    weekdays: None,
+                    from_hour: None,
+                    to_hour: None,
+                    on_holidays: None,
+                    off_peak: Some(true),
+                },
+            ],
+            holidays: HolidayCalendar::default(),
         }
     }
 }
@@ -55,57 +163,141 @@ mod tests {
         Berlin.ymd(2022, 3, 23).and_hms(21, 13, 0)
     }
 
+    fn band_name(time: &DateTime<Tz>) -> String {
+        TariffPackage::default().band_at(time).unwrap().name.clone()
+    }
+
+    /// Winter weekday peak hours on top of day and night rates.
+    fn seasonal_package() -> TariffPackage {
+        let body = r#"
+            name = "Seasonal"
+
+            [[bands]]
+            name = "peak"
+            months = [11, 12, 1, 2, 3]
+            weekdays = ["Mon", "Tue", "Wed", "Thu", "Fri"]
+            from_hour = 9
+            to_hour = 12
+            on_holidays = false
+
+            [[bands]]
+            name = "day"
+            weekdays = ["Mon", "Tue", "Wed", "Thu", "Fri"]
+            from_hour = 7
+            to_hour = 22
+            on_holidays = false
+
+            [[bands]]
+            name = "night"
+            off_peak = true
+        "#;
+        toml::from_str(body).unwrap()
+    }
+
     #[test]
     fn midnight_is_night() {
         let time = mmxxii_23_march().and_hms(0, 0, 0);
-        assert!(Tariff::get_tariff(&time) == Tariff::Night);
+        assert!(band_name(&time) == "night");
     }
 
     #[test]
     fn wed_7() {
         let time = mmxxii_23_march().and_hms(7, 0, 0);
-        assert!(Tariff::get_tariff(&time) == Tariff::Day);
+        assert!(band_name(&time) == "day");
     }
 
     #[test]
     fn wed_6_59() {
         let time = mmxxii_23_march().and_hms(6, 59, 59);
-        assert!(Tariff::get_tariff(&time) == Tariff::Night);
+        assert!(band_name(&time) == "night");
     }
 
     #[test]
     fn wed_22() {
         let time = mmxxii_23_march().and_hms(22, 0, 0);
-        assert!(Tariff::get_tariff(&time) == Tariff::Night);
+        assert!(band_name(&time) == "night");
     }
 
     #[test]
     fn wed_midday() {
         let time = mmxxii_23_march().and_hms(12, 0, 0);
-        assert!(Tariff::get_tariff(&time) == Tariff::Day);
+        assert!(band_name(&time) == "day");
     }
 
     #[test]
     fn sat_midday() {
         let time = mmxxii_26_march().and_hms(12, 0, 0);
-        assert!(Tariff::get_tariff(&time) == Tariff::Night);
+        assert!(band_name(&time) == "night");
     }
 
     #[test]
     fn sat_midnight() {
         let time = mmxxii_26_march().and_hms(0, 0, 0);
-        assert!(Tariff::get_tariff(&time) == Tariff::Night);
+        assert!(band_name(&time) == "night");
     }
 
     #[test]
     fn german_morning_is_correct() {
         let time = german_morning();
-        assert!(Tariff::get_tariff(&time) == Tariff::Day);
+        assert!(band_name(&time) == "day");
     }
 
     #[test]
     fn german_evening_is_correct() {
         let time = german_evening();
-        assert!(Tariff::get_tariff(&time) == Tariff::Night);
+        assert!(band_name(&time) == "night");
+    }
+
+    #[test]
+    fn seasonal_peak_in_winter() {
+        let package = seasonal_package();
+        let winter = Tallinn.with_ymd_and_hms(2022, 3, 23, 10, 0, 0).unwrap();
+        assert!(package.band_at(&winter).unwrap().name == "peak");
+        let summer = Tallinn.with_ymd_and_hms(2022, 6, 15, 10, 0, 0).unwrap();
+        assert!(package.band_at(&summer).unwrap().name == "day");
+        let night = Tallinn.with_ymd_and_hms(2022, 3, 23, 23, 0, 0).unwrap();
+        assert!(package.band_at(&night).unwrap().is_off_peak());
+    }
+
+    #[test]
+    fn holidays_skip_bands() {
+        let package = seasonal_package();
+        let new_years = Tallinn.with_ymd_and_hms(2024, 1, 1, 10, 0, 0).unwrap();
+        assert!(package.band_at(&new_years).unwrap().name == "night");
+    }
+
+    #[test]
+    fn rejects_unknown_rules() {
+        assert!(seasonal_package().validate().is_ok());
+        assert!(TariffPackage::default().validate().is_ok());
+        let mut package = seasonal_package();
+        package.bands[0].weekdays = Some(vec!["Mon".to_owned(), "Wedn".to_owned()]);
+        assert!(package.validate().is_err());
+        let mut package = seasonal_package();
+        package.bands[0].months = Some(vec![0, 1]);
+        assert!(package.validate().is_err());
+        let mut package = seasonal_package();
+        package.bands[1].to_hour = Some(25);
+        assert!(package.validate().is_err());
+        package.bands[1].to_hour = Some(24);
+        assert!(package.validate().is_ok());
+    }
+
+    #[test]
+    fn wraps_past_midnight() {
+        let band = TariffBand {
+            name: "night".to_owned(),
+            months: None,
+            weekdays: None,
+            from_hour: Some(22),
+            to_hour: Some(7),
+            on_holidays: None,
+            off_peak: None,
+        };
+        let holidays = HolidayCalendar::default();
+        let wednesday = |hour| Tallinn.with_ymd_and_hms(2022, 3, 23, hour, 0, 0).unwrap();
+        assert!(band.matches(&wednesday(23), &holidays));
+        assert!(band.matches(&wednesday(3), &holidays));
+        assert!(!band.matches(&wednesday(12), &holidays));
     }
 }
@@ -0,0 +1,204 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use diesel::{prelude::*, PgConnection};
+use eyre::eyre;
+use rust_decimal::Decimal;
+
+use crate::{
+    constants::LOCAL_TZ,
+    price_matrix::{CentsPerKwh, PricePerMwh},
+    schema::tariff_prices,
+    tariff::TariffPackage,
+};
+
+/// Network tariff price of one band of a package, valid from
+/// a moment until the next price of the same band.
+#[derive(Clone, Debug)]
+pub struct TariffRate {
+    pub valid_from: DateTime<Utc>,
+    pub package: String,
+    pub band: String,
+    pub price: CentsPerKwh,
+}
+
+/// Every tariff price, ordered by start.
+#[derive(Clone, Debug, Default)]
+pub struct TariffTable(pub Vec<TariffRate>);
+
+impl TariffTable {
+    pub fn new(mut rates: Vec<TariffRate>) -> TariffTable {
+        rates.sort_by_key(|r| r.valid_from);
+        TariffTable(rates)
+    }
+
+    pub fn load(connection: &mut PgConnection) -> eyre::Result<TariffTable> {
+        use crate::schema::tariff_prices::dsl::*;
+
+        let rows = tariff_prices
+            .select((valid_from, package, band, cents_kwh))
+            .order_by(valid_from)
+            .load::<(DateTime<Utc>, String, String, Decimal)>(connection)?;
+        let rates = rows
+            .into_iter()
+            .map(|(from, package_name, band_name, cents)| TariffRate {
+                valid_from: from,
+                package: package_name,
+                band: band_name,
+                price: CentsPerKwh(cents),
+            })
+            .collect();
+        Ok(TariffTable::new(rates))
+    }
+
+    pub fn rate_at(&self, package: &str, band: &str, moment: &DateTime<Tz>) -> Option<&TariffRate> {
+        self.0
+            .iter()
+            .rev()
+            .find(|r| r.package == package && r.band == band && r.valid_from <= *moment)
+    }
+
+    /// Network tariff price of the given moment in a package, or
+    /// `None` if no band matches or the band has no price yet.
+    pub fn price_at(&self, package: &TariffPackage, moment: &DateTime<Tz>) -> Option<PricePerMwh> {
+        let band = package.band_at(moment)?;
+        self.rate_at(&package.name, &band.name, moment)
+            .map(|rate| PricePerMwh::from(&rate.price))
+    }
+}
+
+/// Start of a tariff price: local midnight of its first day.
+pub fn period_start(date: &NaiveDate) -> eyre::Result<DateTime<Utc>> {
+    date.and_hms_opt(0, 0, 0)
+        .and_then(|dt| dt.and_local_timezone(LOCAL_TZ).earliest())
+        .map(|dt| dt.with_timezone(&Utc))
+        .ok_or(eyre!("No local midnight on {}", date))
+}
+
+/// Adds tariff prices. They apply to stored prices the next
+/// time those are planned.
+pub fn add_rates(connection: &mut PgConnection, rates: &[TariffRate]) -> eyre::Result<usize> {
+    let rows: Vec<_> = rates
+        .iter()
+        .map(|rate| NewTariffPriceDB {
+            valid_from: rate.valid_from,
+            package: &rate.package,
+            band: &rate.band,
+            cents_kwh: &rate.price.0,
+        })
+        .collect();
+    let count = diesel::insert_into(tariff_prices::table)
+        .values(&rows)
+        .execute(connection)?;
+    Ok(count)
+}
+
+#[derive(Insertable)]
+#[table_name = "tariff_prices"]
+pub struct NewTariffPriceDB<'a> {
+    valid_from: DateTime<Utc>,
+    package: &'a str,
+    band: &'a str,
+    cents_kwh: &'a Decimal,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::{
+        constants::MARKET_TZ,
+        database,
+        tariff::{TariffBand, DEFAULT_PACKAGE},
+    };
+
+    fn december_2022() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2022, 12, 1).unwrap()
+    }
+
+    fn rate(valid_from: DateTime<Utc>, band: &str, price: Decimal) -> TariffRate {
+        TariffRate {
+            valid_from,
+            package: DEFAULT_PACKAGE.to_owned(),
+            band: band.to_owned(),
+            price: CentsPerKwh(price),
+        }
+    }
+
+    #[test]
+    fn picks_rate_by_moment() {
+        let december = period_start(&december_2022()).unwrap();
+        let epoch = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+        let table = TariffTable::new(vec![
+            rate(december, "day", dec!(7.68)),
+            rate(december, "night", dec!(4.47)),
+            rate(epoch, "day", dec!(6.65)),
+            rate(epoch, "night", dec!(3.86)),
+        ]);
+        let package = TariffPackage::default();
+        // Wednesday
+        let noon = MARKET_TZ.with_ymd_and_hms(2022, 11, 30, 12, 0, 0).unwrap();
+        assert!(table.price_at(&package, &noon) == Some(PricePerMwh(dec!(66.5))));
+        let noon = noon + chrono::Duration::days(1);
+        assert!(table.price_at(&package, &noon) == Some(PricePerMwh(dec!(76.8))));
+        let night = MARKET_TZ.with_ymd_and_hms(2022, 12, 1, 2, 0, 0).unwrap();
+        assert!(table.price_at(&package, &night) == Some(PricePerMwh(dec!(44.7))));
+        assert!(TariffTable::default().price_at(&package, &night).is_none());
+    }
+
+    #[test]
+    fn prices_only_configured_package() {
+        let epoch = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+        let mut flat = rate(epoch, "all", dec!(5));
+        flat.package = "Flat".to_owned();
+        let table = TariffTable::new(vec![rate(epoch, "day", dec!(6)), flat]);
+        let package = TariffPackage {
+            name: "Flat".to_owned(),
+            bands: vec![TariffBand {
+                name: "all".to_owned(),
+                months: None,
+                weekdays: None,
+                from_hour: None,
+                to_hour: None,
+                on_holidays: None,
+                off_peak: None,
+            }],
+            holidays: Default::default(),
+        };
+        let noon = MARKET_TZ.with_ymd_and_hms(2022, 11, 30, 12, 0, 0).unwrap();
+        assert!(table.price_at(&package, &noon) == Some(PricePerMwh(dec!(50))));
+    }
+
+    #[test]
+    #[serial]
+    fn seeds_december_2022_night() {
+        let mut connection = database::establish_connection();
+        let table = TariffTable::load(&mut connection).unwrap();
+        let package = TariffPackage::default();
+        let night = MARKET_TZ.with_ymd_and_hms(2022, 12, 14, 2, 0, 0).unwrap();
+        assert!(table.price_at(&package, &night) == Some(PricePerMwh(dec!(44.7))));
+        let night = MARKET_TZ.with_ymd_and_hms(2023, 2, 14, 2, 0, 0).unwrap();
+        assert!(table.price_at(&package, &night) == Some(PricePerMwh(dec!(50.5))));
+    }
+
+    #[test]
+    #[serial]
+    fn adds_rates() {
+        let mut connection = database::establish_connection();
+        connection.test_transaction::<_, eyre::Error, _>(|connection| {
+            let date = NaiveDate::from_ymd_opt(2040, 1, 1).unwrap();
+            let from = period_start(&date)?;
+            let added = add_rates(connection, &[rate(from, "night", dec!(5))])?;
+            assert!(added == 1);
+            let table = TariffTable::load(connection)?;
+            let package = TariffPackage::default();
+            let night = MARKET_TZ.with_ymd_and_hms(2040, 1, 3, 2, 0, 0).unwrap();
+            assert!(table.price_at(&package, &night) == Some(PricePerMwh(dec!(50))));
+            let noon = MARKET_TZ.with_ymd_and_hms(2040, 1, 3, 12, 0, 0).unwrap();
+            assert!(table.price_at(&package, &noon) == Some(PricePerMwh(dec!(86.8))));
+            Ok(())
+        });
+    }
+}